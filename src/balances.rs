@@ -1,8 +1,13 @@
 use std::collections::HashMap;
 use subxt::{
+    ext::{
+        sp_core::H256,
+        sp_runtime::AccountId32,
+    },
     OnlineClient,
     PolkadotConfig,
 };
+use crate::blocks;
 
 #[subxt::subxt(runtime_metadata_path = "./data/metadata.scale")]
 pub mod polkadot {}
@@ -15,4 +20,20 @@ pub async fn dump_balances(api: &OnlineClient<PolkadotConfig>) -> Result<HashMap
         data.insert(hex::encode(key), (account.data.free,account.data.reserved,account.data.misc_frozen,account.data.fee_frozen));
     }
     Ok(data)
-}
\ No newline at end of file
+}
+
+/// Free, reserved, misc frozen and fee frozen balances of a single account,
+/// at the given block or at the best block if `None`.
+pub async fn account_data(api: &OnlineClient<PolkadotConfig>, account_id: &AccountId32, at: Option<H256>) -> Result<(u128,u128,u128,u128), Box<dyn std::error::Error>> {
+    let address = polkadot::storage().system().account(account_id);
+    let account = api.storage().fetch_or_default(&address, at).await?;
+    Ok((account.data.free,account.data.reserved,account.data.misc_frozen,account.data.fee_frozen))
+}
+
+/// Free and reserved balances of an account before and after the block with the given hash.
+pub async fn balance_deltas(api: &OnlineClient<PolkadotConfig>, acc_id: &AccountId32, hash: H256) -> Result<(i128, i128), Box<dyn std::error::Error>> {
+    let parent = blocks::parent_hash(api, hash).await?;
+    let (free_before, reserved_before, ..) = account_data(api, acc_id, Some(parent)).await?;
+    let (free_after, reserved_after, ..) = account_data(api, acc_id, Some(hash)).await?;
+    Ok((free_after as i128 - free_before as i128, reserved_after as i128 - reserved_before as i128))
+}
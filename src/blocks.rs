@@ -0,0 +1,82 @@
+use futures::StreamExt;
+use subxt::{
+    events::{Events, StaticEvent},
+    ext::sp_core::H256,
+    OnlineClient,
+    PolkadotConfig,
+};
+
+/// Number of the block with the given hash, or of the best block if `None`.
+pub async fn block_number(api: &OnlineClient<PolkadotConfig>, hash: Option<H256>) -> Result<u32, Box<dyn std::error::Error>> {
+    let header = api.rpc().header(hash).await?.expect("the block has been imported; qed");
+    Ok(header.number)
+}
+
+/// Waits for the next `nb_blocks` finalized blocks.
+pub async fn wait_blocks(api: &OnlineClient<PolkadotConfig>, nb_blocks: u32) -> Result<(), Box<dyn std::error::Error>> {
+    let mut sub = api.events().subscribe_finalized().await?;
+    for _ in 0..nb_blocks {
+        if let Some(events) = sub.next().await {
+            events?;
+        }
+    }
+    Ok(())
+}
+
+/// Returns the events of the first finalized block emitting an `E` event,
+/// or `None` if no such block was finalized within `max_blocks` blocks.
+pub async fn wait_for_event<E: StaticEvent>(api: &OnlineClient<PolkadotConfig>, max_blocks: u32) -> Result<Option<Events<PolkadotConfig>>, Box<dyn std::error::Error>> {
+    let mut sub = api.events().subscribe_finalized().await?;
+    for _ in 0..max_blocks {
+        match sub.next().await {
+            Some(events) => {
+                let events = events?;
+                if events.has::<E>()? {
+                    return Ok(Some(events));
+                }
+            },
+            None => break,
+        }
+    }
+    Ok(None)
+}
+
+/// Returns the first `E` event satisfying `filter` in the next `max_blocks` finalized blocks,
+/// along with the hash of the block emitting it.
+pub async fn wait_for_matching_event<E: StaticEvent, F: Fn(&E) -> bool>(api: &OnlineClient<PolkadotConfig>, max_blocks: u32, filter: F) -> Result<Option<(E, H256)>, Box<dyn std::error::Error>> {
+    let mut sub = api.events().subscribe_finalized().await?;
+    for _ in 0..max_blocks {
+        match sub.next().await {
+            Some(events) => {
+                let events = events?;
+                for event in events.find::<E>() {
+                    let event = event?;
+                    if filter(&event) {
+                        return Ok(Some((event, events.block_hash())));
+                    }
+                }
+            },
+            None => break,
+        }
+    }
+    Ok(None)
+}
+
+/// Hash of the parent of the block with the given hash.
+pub async fn parent_hash(api: &OnlineClient<PolkadotConfig>, hash: H256) -> Result<H256, Box<dyn std::error::Error>> {
+    let header = api.rpc().header(Some(hash)).await?.expect("the block has been imported; qed");
+    Ok(header.parent_hash)
+}
+
+/// Waits for the block with the given number to be finalized and returns its hash.
+pub async fn wait_for_block(api: &OnlineClient<PolkadotConfig>, number: u32) -> Result<H256, Box<dyn std::error::Error>> {
+    loop {
+        let finalized = api.rpc().finalized_head().await?;
+        if block_number(api, Some(finalized)).await? >= number {
+            let hash = api.rpc().block_hash(Some(number.into())).await?
+                .expect("the block has been finalized; qed");
+            return Ok(hash);
+        }
+        wait_blocks(api, 1).await?;
+    }
+}
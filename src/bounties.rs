@@ -0,0 +1,256 @@
+use codec::Encode;
+use subxt::{
+    ext::{
+        sp_core::{sr25519, Pair},
+        sp_runtime::AccountId32,
+    },
+    OnlineClient,
+    PolkadotConfig,
+};
+use crate::{balances, blocks, council, errors, treasury};
+use crate::tx::{submit, sudo};
+
+#[subxt::subxt(runtime_metadata_path = "./data/metadata.scale")]
+pub mod polkadot {}
+
+type Call = polkadot::runtime_types::edgeware_runtime::Call;
+type BountiesCall = polkadot::runtime_types::pallet_bounties::pallet::Call;
+type UtilityCall = polkadot::runtime_types::pallet_utility::pallet::Call;
+type BountyStatus = polkadot::runtime_types::pallet_bounties::BountyStatus<AccountId32, u32>;
+
+/// Account holding the funds of a bounty, derived from the treasury pallet id.
+pub fn bounty_account(api: &OnlineClient<PolkadotConfig>, bounty_id: u32) -> Result<AccountId32, Box<dyn std::error::Error>> {
+    let pallet_id = api.constants().at(&polkadot::constants().treasury().pallet_id())?;
+    let encoded = (*b"modl", pallet_id.0, "bt", bounty_id).encode();
+    let mut raw = [0u8; 32];
+    raw[..encoded.len()].copy_from_slice(&encoded);
+    Ok(AccountId32::from(raw))
+}
+
+/// Bond reserved from the proposer of a bounty with the given description.
+pub fn bounty_bond(api: &OnlineClient<PolkadotConfig>, description: &[u8]) -> Result<u128, Box<dyn std::error::Error>> {
+    let base = api.constants().at(&polkadot::constants().bounties().bounty_deposit_base())?;
+    let per_byte = api.constants().at(&polkadot::constants().bounties().data_deposit_per_byte())?;
+    Ok(base + per_byte * description.len() as u128)
+}
+
+/// Deposit reserved from a curator accepting a bounty with the given fee.
+pub fn curator_deposit(api: &OnlineClient<PolkadotConfig>, fee: u128) -> Result<u128, Box<dyn std::error::Error>> {
+    let multiplier = api.constants().at(&polkadot::constants().bounties().curator_deposit_multiplier())?;
+    let deposit_max = api.constants().at(&polkadot::constants().bounties().curator_deposit_max())?;
+    let deposit_min = api.constants().at(&polkadot::constants().bounties().curator_deposit_min())?;
+    let deposit = treasury::permill_of(multiplier.0, fee);
+    let deposit = deposit_max.map_or(deposit, |m| deposit.min(m));
+    Ok(deposit_min.map_or(deposit, |m| deposit.max(m)))
+}
+
+/// Has the council dispatch all the given bounty calls in one motion.
+async fn council_bounty_calls(api: &OnlineClient<PolkadotConfig>, acc_seed_accounts : &[sr25519::Pair], calls: Vec<BountiesCall>) -> Result<(), Box<dyn std::error::Error>> {
+    let calls = calls.into_iter().map(Call::Bounties).collect();
+    let call = Call::Utility(UtilityCall::batch_all { calls });
+    council::pass_motion(api, acc_seed_accounts, &call.encode()).await?;
+    Ok(())
+}
+
+/// Current status of a bounty.
+async fn status(api: &OnlineClient<PolkadotConfig>, bounty_id: u32) -> Result<BountyStatus, Box<dyn std::error::Error>> {
+    let bounty = api.storage().fetch(&polkadot::storage().bounties().bounties(&bounty_id), None).await?
+        .expect("ERROR: Bounty incorrectly registered");
+    Ok(bounty.status)
+}
+
+/// Proposes a bounty and checks the bond reserved from the proposer.
+async fn propose(api: &OnlineClient<PolkadotConfig>, proposer: &sr25519::Pair, value: u128, description: &[u8]) -> Result<u32, Box<dyn std::error::Error>> {
+    let acc_id: AccountId32 = proposer.public().into();
+    let tx = polkadot::tx().bounties().propose_bounty(value, description.to_vec());
+    let events = submit(api, proposer, &tx).await?;
+    let proposed = events.find_first::<polkadot::bounties::events::BountyProposed>()?
+        .expect("ERROR: Bounty incorrectly registered");
+    let hash = events.block_hash();
+    let bond = bounty_bond(api, description)?;
+    let (_, reserved) = balances::balance_deltas(api, &acc_id, hash).await?;
+    assert_eq!(reserved, bond as i128);
+    let bounty = api.storage().fetch(&polkadot::storage().bounties().bounties(&proposed.index), Some(hash)).await?
+        .expect("ERROR: Bounty incorrectly registered");
+    assert_eq!(bounty.proposer, acc_id);
+    assert_eq!(bounty.value, value);
+    assert_eq!(bounty.bond, bond);
+    assert!(matches!(bounty.status, BountyStatus::Proposed));
+    let stored = api.storage().fetch(&polkadot::storage().bounties().bounty_descriptions(&proposed.index), Some(hash)).await?
+        .expect("ERROR: Bounty description incorrectly registered");
+    assert_eq!(stored.0, description);
+    println!("Bounty {} proposed with bond {}",proposed.index, bond);
+    Ok(proposed.index)
+}
+
+/// Accepts the curation of a bounty and checks the curator deposit.
+async fn accept(api: &OnlineClient<PolkadotConfig>, curator: &sr25519::Pair, bounty_id: u32, fee: u128) -> Result<u128, Box<dyn std::error::Error>> {
+    let acc_id: AccountId32 = curator.public().into();
+    let events = submit(api, curator, &polkadot::tx().bounties().accept_curator(bounty_id)).await?;
+    let hash = events.block_hash();
+    let deposit = curator_deposit(api, fee)?;
+    let (_, reserved) = balances::balance_deltas(api, &acc_id, hash).await?;
+    assert_eq!(reserved, deposit as i128);
+    let bounty = api.storage().fetch(&polkadot::storage().bounties().bounties(&bounty_id), Some(hash)).await?
+        .expect("ERROR: Bounty incorrectly registered");
+    assert_eq!(bounty.fee, fee);
+    assert_eq!(bounty.curator_deposit, deposit);
+    match bounty.status {
+        BountyStatus::Active { curator, .. } => assert_eq!(curator, acc_id),
+        status => panic!("ERROR: Bounty {} not active after the curator accepted: {:?}", bounty_id, status),
+    }
+    println!("Curator of bounty {} accepted with deposit {}",bounty_id, deposit);
+    Ok(deposit)
+}
+
+/// Runs bounties from their proposal to their payout, along with rejection, curator
+/// unassignment and cancellation.
+pub async fn bounty_lifecycle(api: &OnlineClient<PolkadotConfig>, acc_seed_accounts : &[sr25519::Pair], sudo_seed_account: &sr25519::Pair) -> Result<(), Box<dyn std::error::Error>> {
+    let value = api.constants().at(&polkadot::constants().bounties().bounty_value_minimum())? * 10;
+    let fee = value / 10;
+    let description = b"Edgeware node tests bounty".to_vec();
+    let spend_period = api.constants().at(&polkadot::constants().treasury().spend_period())?;
+    let payout_delay = api.constants().at(&polkadot::constants().bounties().bounty_deposit_payout_delay())?;
+    treasury::fund(api, sudo_seed_account, 3 * value).await?;
+    let (proposer, curator, beneficiary) = (&acc_seed_accounts[12], &acc_seed_accounts[13], &acc_seed_accounts[14]);
+    let (other_proposer, other_curator, rejected_proposer) = (&acc_seed_accounts[15], &acc_seed_accounts[16], &acc_seed_accounts[17]);
+    let curator_id: AccountId32 = curator.public().into();
+    let beneficiary_id: AccountId32 = beneficiary.public().into();
+    let other_curator_id: AccountId32 = other_curator.public().into();
+    let rejected_id: AccountId32 = rejected_proposer.public().into();
+    // A bounty closed before its approval forfeits the proposer's bond.
+    let rejected = propose(api, rejected_proposer, value, &description).await?;
+    let call = Call::Bounties(BountiesCall::close_bounty { bounty_id: rejected });
+    let events = sudo(api, sudo_seed_account, call).await?;
+    let bounty_rejected = events.find_first::<polkadot::bounties::events::BountyRejected>()?
+        .expect("ERROR: Bounty not rejected");
+    let bond = bounty_bond(api, &description)?;
+    assert_eq!(bounty_rejected.index, rejected);
+    assert_eq!(bounty_rejected.bond, bond);
+    assert_eq!(balances::balance_deltas(api, &rejected_id, events.block_hash()).await?, (0, -(bond as i128)));
+    let remaining = api.storage().fetch(&polkadot::storage().bounties().bounties(&rejected), Some(events.block_hash())).await?;
+    assert!(remaining.is_none());
+    println!("Bounty {} rejected, bond {} forfeited",rejected, bond);
+    // The council approves two bounties, which are funded at the next spend period.
+    let bounty_id = propose(api, proposer, value, &description).await?;
+    let other_id = propose(api, other_proposer, value, &description).await?;
+    council_bounty_calls(api, acc_seed_accounts, vec![
+        BountiesCall::approve_bounty { bounty_id },
+        BountiesCall::approve_bounty { bounty_id: other_id },
+    ]).await?;
+    assert!(matches!(status(api, bounty_id).await?, BountyStatus::Approved));
+    assert!(matches!(status(api, other_id).await?, BountyStatus::Approved));
+    let approvals = api.storage().fetch_or_default(&polkadot::storage().bounties().bounty_approvals(), None).await?;
+    assert!(approvals.0.contains(&bounty_id) && approvals.0.contains(&other_id));
+    let (_, hash) = blocks::wait_for_matching_event::<polkadot::bounties::events::BountyBecameActive, _>(
+        api,
+        spend_period + 2,
+        |e| e.index == bounty_id,
+    ).await?.expect("ERROR: Bounty not funded");
+    let events = api.events().at(Some(hash)).await?;
+    let mut activated = Vec::new();
+    for active in events.find::<polkadot::bounties::events::BountyBecameActive>() {
+        activated.push(active?.index);
+    }
+    assert!(activated.contains(&other_id));
+    for id in [bounty_id, other_id] {
+        let bounty = api.storage().fetch(&polkadot::storage().bounties().bounties(&id), Some(hash)).await?
+            .expect("ERROR: Bounty incorrectly registered");
+        assert!(matches!(bounty.status, BountyStatus::Funded));
+        let (free, ..) = balances::account_data(api, &bounty_account(api, id)?, Some(hash)).await?;
+        assert_eq!(free, value);
+        let (_, reserved) = balances::balance_deltas(api, &bounty.proposer, hash).await?;
+        assert_eq!(reserved, -(bounty.bond as i128));
+        println!("Bounty {} funded with {}, bond {} returned",id, value, bounty.bond);
+    }
+    // The council proposes the curators.
+    council_bounty_calls(api, acc_seed_accounts, vec![
+        BountiesCall::propose_curator { bounty_id, curator: curator_id.clone().into(), fee },
+        BountiesCall::propose_curator { bounty_id: other_id, curator: other_curator_id.clone().into(), fee },
+    ]).await?;
+    match status(api, bounty_id).await? {
+        BountyStatus::CuratorProposed { curator } => assert_eq!(curator, curator_id),
+        status => panic!("ERROR: Bounty {} curator not proposed: {:?}", bounty_id, status),
+    }
+    // The curator accepts and awards the bounty.
+    let deposit = accept(api, curator, bounty_id, fee).await?;
+    let tx = polkadot::tx().bounties().award_bounty(bounty_id, beneficiary_id.clone().into());
+    let events = submit(api, curator, &tx).await?;
+    let awarded = events.find_first::<polkadot::bounties::events::BountyAwarded>()?
+        .expect("ERROR: Bounty not awarded");
+    assert_eq!(awarded.index, bounty_id);
+    assert_eq!(awarded.beneficiary, beneficiary_id);
+    let awarded_at = blocks::block_number(api, Some(events.block_hash())).await?;
+    let unlock_at = match status(api, bounty_id).await? {
+        BountyStatus::PendingPayout { curator, beneficiary, unlock_at } => {
+            assert_eq!(curator, curator_id);
+            assert_eq!(beneficiary, beneficiary_id);
+            assert_eq!(unlock_at, awarded_at + payout_delay);
+            unlock_at
+        },
+        status => panic!("ERROR: Bounty {} not pending payout: {:?}", bounty_id, status),
+    };
+    println!("Bounty {} awarded, payout unlocked at block {}",bounty_id, unlock_at);
+    // The payout can only be claimed after the delay, by anyone.
+    let result = submit(api, proposer, &polkadot::tx().bounties().claim_bounty(bounty_id)).await;
+    errors::assert_module_error(api, result, "Premature");
+    let now = blocks::block_number(api, None).await?;
+    if unlock_at >= now {
+        blocks::wait_blocks(api, unlock_at - now + 1).await?;
+    }
+    let events = submit(api, proposer, &polkadot::tx().bounties().claim_bounty(bounty_id)).await?;
+    let claimed = events.find_first::<polkadot::bounties::events::BountyClaimed>()?
+        .expect("ERROR: Bounty not claimed");
+    assert_eq!(claimed.index, bounty_id);
+    assert_eq!(claimed.beneficiary, beneficiary_id);
+    assert_eq!(claimed.payout, value - fee);
+    let hash = events.block_hash();
+    let (free, _) = balances::balance_deltas(api, &beneficiary_id, hash).await?;
+    assert_eq!(free, (value - fee) as i128);
+    let (curator_free, curator_reserved) = balances::balance_deltas(api, &curator_id, hash).await?;
+    assert_eq!((curator_free, curator_reserved), ((fee + deposit) as i128, -(deposit as i128)));
+    let remaining = api.storage().fetch(&polkadot::storage().bounties().bounties(&bounty_id), Some(hash)).await?;
+    assert!(remaining.is_none());
+    let remaining = api.storage().fetch(&polkadot::storage().bounties().bounty_descriptions(&bounty_id), Some(hash)).await?;
+    assert!(remaining.is_none());
+    println!("Bounty {} claimed: {} paid to {}, fee {} to the curator",bounty_id, claimed.payout, beneficiary_id, fee);
+    // A curator unassigning themself gets the deposit back.
+    let deposit = accept(api, other_curator, other_id, fee).await?;
+    let events = submit(api, other_curator, &polkadot::tx().bounties().unassign_curator(other_id)).await?;
+    let (_, reserved) = balances::balance_deltas(api, &other_curator_id, events.block_hash()).await?;
+    assert_eq!(reserved, -(deposit as i128));
+    assert!(matches!(status(api, other_id).await?, BountyStatus::Funded));
+    println!("Curator of bounty {} unassigned themself, deposit {} returned",other_id, deposit);
+    // An active curator unassigned by root forfeits the deposit.
+    council_bounty_calls(api, acc_seed_accounts, vec![
+        BountiesCall::propose_curator { bounty_id: other_id, curator: other_curator_id.clone().into(), fee },
+    ]).await?;
+    let deposit = accept(api, other_curator, other_id, fee).await?;
+    let call = Call::Bounties(BountiesCall::unassign_curator { bounty_id: other_id });
+    let events = sudo(api, sudo_seed_account, call).await?;
+    assert_eq!(balances::balance_deltas(api, &other_curator_id, events.block_hash()).await?, (0, -(deposit as i128)));
+    assert!(matches!(status(api, other_id).await?, BountyStatus::Funded));
+    println!("Curator of bounty {} unassigned by root, deposit {} slashed",other_id, deposit);
+    // Closing a funded bounty returns its funds to the treasury.
+    let call = Call::Bounties(BountiesCall::close_bounty { bounty_id: other_id });
+    let events = sudo(api, sudo_seed_account, call).await?;
+    let canceled = events.find_first::<polkadot::bounties::events::BountyCanceled>()?
+        .expect("ERROR: Bounty not canceled");
+    assert_eq!(canceled.index, other_id);
+    let other_account = bounty_account(api, other_id)?;
+    let treasury_account = treasury::treasury_account(api)?;
+    let mut returned = 0;
+    for transfer in events.find::<polkadot::balances::events::Transfer>() {
+        let transfer = transfer?;
+        if transfer.from == other_account && transfer.to == treasury_account {
+            returned += transfer.amount;
+        }
+    }
+    assert_eq!(returned, value);
+    let (free, ..) = balances::account_data(api, &other_account, Some(events.block_hash())).await?;
+    assert_eq!(free, 0);
+    let remaining = api.storage().fetch(&polkadot::storage().bounties().bounties(&other_id), Some(events.block_hash())).await?;
+    assert!(remaining.is_none());
+    println!("Bounty {} canceled, {} returned to the treasury",other_id, returned);
+    Ok(())
+}
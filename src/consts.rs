@@ -3,8 +3,10 @@ pub const NB_TEST_ACCOUNTS: u32 = 260;
 pub const NB_COUNCILLOR_CANDIDATES: u32 = 30;
 pub const TEST_ACCOUNT_FUNDING: u128 = 100_000 * EDG;
 pub const EXISTENTIAL_DEPOSIT: u128 = 10_000_000_000_000;
-pub const WEIGHT_BOUND: u64 = 10_000_000_000;
-pub const LENGTH_BOUND: u32 = 100_000;
 pub const BLOCK_INCLUSION_LAG: u64 = 13;
+pub const MAX_MOTION_DURATION: u32 = 600;
+pub const MAX_ERA_DURATION: u32 = 1_800;
 pub const NB_VOTERS: u32 = 30;
-pub const WASM_BINARY: Option<&[u8]> = Some(include_bytes!("../data/edgeware_runtime.compact.compressed.wasm"));
\ No newline at end of file
+pub const PHRAGMEN_MAXIMUM_VOTE: usize = 16;
+pub const WASM_BINARY: Option<&[u8]> = Some(include_bytes!("../data/edgeware_runtime.compact.compressed.wasm"));
+pub const NB_PUBLIC_PROPOSALS: u32 = 4;
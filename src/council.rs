@@ -1,24 +1,31 @@
+use std::cmp::{max, min};
 use std::time::Duration;
-use codec::Encode;
+use codec::{Decode, Encode};
 use subxt::{
+    rpc::{rpc_params, ClientT},
     tx::{
         Era,
         PairSigner,
         PlainTip,
         PolkadotExtrinsicParamsBuilder as Params,
+        TxEvents,
+        TxPayload,
     },
     ext::{
-        sp_core::{sr25519, Pair},
+        sp_core::{sr25519, Pair, H256},
         sp_runtime::{
             AccountId32,
             traits::{BlakeTwo256, Hash}
         },
     },
+    Metadata,
     OnlineClient,
     PolkadotConfig,
 };
 use rand::Rng;
 use crate::consts::*;
+use crate::{balances, blocks, errors, treasury};
+use crate::tx::{submit, sudo};
 
 #[subxt::subxt(runtime_metadata_path = "./data/metadata.scale")]
 pub mod polkadot {}
@@ -27,12 +34,20 @@ type Call = polkadot::runtime_types::edgeware_runtime::Call;
 type DemocracyCall = polkadot::runtime_types::pallet_democracy::pallet::Call;
 type TreasuryCall = polkadot::runtime_types::pallet_treasury::pallet::Call;
 type RenouncingCandidacy = polkadot::runtime_types::pallet_elections_phragmen::Renouncing;
+type CouncilCall = polkadot::runtime_types::pallet_collective::pallet::Call;
+type UtilityCall = polkadot::runtime_types::pallet_utility::pallet::Call;
 
 pub async fn populate_council(api: &OnlineClient<PolkadotConfig>, acc_seed_accounts : &[sr25519::Pair]) -> Result<(), Box<dyn std::error::Error>> {
     let tx_params = Params::new()
         .tip(PlainTip::new(0))
         .era(Era::Immortal, api.genesis_hash());
+    let candidacy_bond = api.constants().at(&polkadot::constants().phragmen_election().candidacy_bond())?;
+    let voting_bond_base = api.constants().at(&polkadot::constants().phragmen_election().voting_bond_base())?;
+    let voting_bond_factor = api.constants().at(&polkadot::constants().phragmen_election().voting_bond_factor())?;
+    let acc_ids: Vec<AccountId32> = acc_seed_accounts[..NB_COUNCILLOR_CANDIDATES as usize].iter().map(|x| x.public().into()).collect();
     // All councillors renounce candidacy
+    let members = api.storage().fetch_or_default(&polkadot::storage().phragmen_election().members(), None).await?;
+    let reserved_before = reserved_balances(api, &acc_ids, None).await?;
     let tx = polkadot::tx().phragmen_election().renounce_candidacy(RenouncingCandidacy::Member);
     for i in 0..NB_COUNCILLOR_CANDIDATES {
         let acc_signer = PairSigner::new(acc_seed_accounts[i as usize].clone());
@@ -40,7 +55,16 @@ pub async fn populate_council(api: &OnlineClient<PolkadotConfig>, acc_seed_accou
         let hash = api.tx().sign_and_submit(&tx, &acc_signer, tx_params).await?;
         println!("Councillor membership removal extrinsic submitted for test account {}: {}",i, hash);
     }
+    tokio::time::sleep(Duration::from_secs(BLOCK_INCLUSION_LAG)).await;
+    // Renouncing members get their deposit back.
+    let expected: Vec<i128> = acc_ids.iter()
+        .map(|a| members.iter().find(|m| m.who == *a).map_or(0, |m| -(m.deposit as i128)))
+        .collect();
+    let reserved_after = reserved_balances(api, &acc_ids, None).await?;
+    check_reserved("renouncing membership", &acc_ids, &reserved_before, &reserved_after, &expected);
     // Submit Candidacy to the council
+    let candidates_before = api.storage().fetch_or_default(&polkadot::storage().phragmen_election().candidates(), None).await?;
+    let reserved_before = reserved_after;
     for i in 0..NB_COUNCILLOR_CANDIDATES {
         let tx = polkadot::tx().phragmen_election().submit_candidacy(i as u32);
         let acc_signer = PairSigner::new(acc_seed_accounts[i as usize].clone());
@@ -48,6 +72,25 @@ pub async fn populate_council(api: &OnlineClient<PolkadotConfig>, acc_seed_accou
         let hash = api.tx().sign_and_submit(&tx, &acc_signer, tx_params).await?;
         println!("Councillor candidacy extrinsic submitted for test account {}: {}",i, hash);
     }
+    tokio::time::sleep(Duration::from_secs(BLOCK_INCLUSION_LAG)).await;
+    // New candidates reserve the candidacy bond.
+    let candidates = api.storage().fetch_or_default(&polkadot::storage().phragmen_election().candidates(), None).await?;
+    let expected: Vec<i128> = acc_ids.iter()
+        .map(|a| if candidates.iter().any(|c| c.0 == *a) && !candidates_before.iter().any(|c| c.0 == *a) { candidacy_bond as i128 } else { 0 })
+        .collect();
+    let reserved_after = reserved_balances(api, &acc_ids, None).await?;
+    check_reserved("submitting candidacy", &acc_ids, &reserved_before, &reserved_after, &expected);
+    for (c, deposit) in candidates.iter() {
+        if acc_ids.contains(c) {
+            assert_eq!(*deposit, candidacy_bond);
+        }
+    }
+    let mut voters_before = Vec::new();
+    for acc_id in acc_ids.iter() {
+        voters_before.push(api.storage().fetch_or_default(&polkadot::storage().phragmen_election().voting(acc_id), None).await?);
+    }
+    let reserved_before = reserved_after;
+    let mut nb_votes = Vec::new();
     let mut rng = rand::thread_rng();
     for i in 0..NB_COUNCILLOR_CANDIDATES {
         let n: i32 = rng.gen_range(0..10);
@@ -58,22 +101,75 @@ pub async fn populate_council(api: &OnlineClient<PolkadotConfig>, acc_seed_accou
         }
         votes.sort();
         votes.dedup();
+        nb_votes.push(votes.len());
         let tx = polkadot::tx().phragmen_election().vote(votes, TEST_ACCOUNT_FUNDING / 10);
         let acc_signer = PairSigner::new(acc_seed_accounts[i as usize].clone());
         // submit the transaction:
         let hash = api.tx().sign_and_submit(&tx, &acc_signer, tx_params).await?;
         println!("Councillor vote extrinsic submitted for test account {}: {}",i, hash);
     }
+    tokio::time::sleep(Duration::from_secs(BLOCK_INCLUSION_LAG)).await;
+    // Voters reserve the voting bond base plus the voting bond factor per vote, empty votes are rejected.
+    let mut expected = Vec::new();
+    for (k, acc_id) in acc_ids.iter().enumerate() {
+        let voter = api.storage().fetch_or_default(&polkadot::storage().phragmen_election().voting(acc_id), None).await?;
+        if 0 < nb_votes[k] {
+            assert_eq!(voter.deposit, voting_bond_base + voting_bond_factor * nb_votes[k] as u128);
+        } else {
+            assert_eq!(voter.deposit, voters_before[k].deposit);
+        }
+        expected.push(voter.deposit as i128 - voters_before[k].deposit as i128);
+    }
+    let reserved_after = reserved_balances(api, &acc_ids, None).await?;
+    check_reserved("voting", &acc_ids, &reserved_before, &reserved_after, &expected);
     // Second candidate renounce candidacy
+    let reserved_before = reserved_after;
     let i = 2;
-    let tx = polkadot::tx().phragmen_election().renounce_candidacy(RenouncingCandidacy::Candidate(i));
-    let acc_signer = PairSigner::new(acc_seed_accounts[i as usize].clone());
-    // submit the transaction:
-    let hash = api.tx().sign_and_submit(&tx, &acc_signer, tx_params).await?;
-    println!("Councillor candidacy removal extrinsic submitted for test account {}: {}",i, hash);
-    // Get the councillors after 6 mins.
-    tokio::time::sleep(Duration::from_secs(60 * 6)).await;
+    assert!(candidates.iter().any(|c| c.0 == acc_ids[i]), "ERROR: Test account {} is not a candidate", i);
+    let tx = polkadot::tx().phragmen_election().renounce_candidacy(RenouncingCandidacy::Candidate(candidates.len() as u32));
+    // submit the transaction and wait for its finalization, so that a failure is reported as such:
+    submit(api, &acc_seed_accounts[i], &tx).await?;
+    println!("Councillor candidacy renounced for test account {}",i);
+    let expected: Vec<i128> = acc_ids.iter()
+        .map(|a| if *a == acc_ids[i] { -(candidacy_bond as i128) } else { 0 })
+        .collect();
+    let reserved_after = reserved_balances(api, &acc_ids, None).await?;
+    check_reserved("renouncing candidacy", &acc_ids, &reserved_before, &reserved_after, &expected);
+    // Everybody running in the election and not retaining a seat loses their bond.
+    let mut running = api.storage().fetch_or_default(&polkadot::storage().phragmen_election().candidates(), None).await?;
+    let members = api.storage().fetch_or_default(&polkadot::storage().phragmen_election().members(), None).await?;
+    let runners_up = api.storage().fetch_or_default(&polkadot::storage().phragmen_election().runners_up(), None).await?;
+    running.extend(members.into_iter().chain(runners_up.into_iter()).map(|s| (s.who, s.deposit)));
+    let reserved_before = reserved_after;
+    // Get the councillors after the next election.
+    if let Some(election_hash) = verify_election(api).await? {
+        let at = Some(election_hash);
+        let members = api.storage().fetch_or_default(&polkadot::storage().phragmen_election().members(), at).await?;
+        let runners_up = api.storage().fetch_or_default(&polkadot::storage().phragmen_election().runners_up(), at).await?;
+        let seat_holders: Vec<AccountId32> = members.iter().chain(runners_up.iter()).map(|s| s.who.clone()).collect();
+        let slashed: Vec<polkadot::phragmen_election::events::CandidateSlashed> = api.events().at(at).await?
+            .find::<polkadot::phragmen_election::events::CandidateSlashed>()
+            .collect::<Result<_, _>>()?;
+        for (c, deposit) in running.iter() {
+            let slash = slashed.iter().find(|e| e.candidate == *c);
+            if seat_holders.contains(c) {
+                assert!(slash.is_none());
+            } else {
+                assert_eq!(slash.map(|e| e.amount), Some(*deposit));
+            }
+        }
+        let expected: Vec<i128> = acc_ids.iter()
+            .map(|a| match running.iter().find(|c| c.0 == *a) {
+                Some((_, deposit)) if !seat_holders.contains(a) => -(*deposit as i128),
+                _ => 0,
+            })
+            .collect();
+        let reserved_after = reserved_balances(api, &acc_ids, at).await?;
+        check_reserved("the election", &acc_ids, &reserved_before, &reserved_after, &expected);
+    }
     // Drop 3 councillors so that 3 runner ups take the seats.
+    let members = api.storage().fetch_or_default(&polkadot::storage().phragmen_election().members(), None).await?;
+    let reserved_before = reserved_balances(api, &acc_ids, None).await?;
     for i in 0..3 {
         let tx = polkadot::tx().phragmen_election().renounce_candidacy(RenouncingCandidacy::Member);
         let acc_signer = PairSigner::new(acc_seed_accounts[i as usize].clone());
@@ -81,12 +177,186 @@ pub async fn populate_council(api: &OnlineClient<PolkadotConfig>, acc_seed_accou
         let hash = api.tx().sign_and_submit(&tx, &acc_signer, tx_params).await?;
         println!("Councillor membership removal extrinsic submitted for test account {}: {}",i, hash);
     }
+    // The last candidate stops voting.
+    let i = NB_COUNCILLOR_CANDIDATES as usize - 1;
+    let voter = api.storage().fetch_or_default(&polkadot::storage().phragmen_election().voting(&acc_ids[i]), None).await?;
+    let tx = polkadot::tx().phragmen_election().remove_voter();
+    let acc_signer = PairSigner::new(acc_seed_accounts[i].clone());
+    // submit the transaction:
+    let hash = api.tx().sign_and_submit(&tx, &acc_signer, tx_params).await?;
+    println!("Councillor voter removal extrinsic submitted for test account {}: {}",i, hash);
     tokio::time::sleep(Duration::from_secs(BLOCK_INCLUSION_LAG)).await;
+    let expected: Vec<i128> = acc_ids.iter().enumerate()
+        .map(|(k, a)| {
+            let released = if k < 3 { members.iter().find(|m| m.who == *a).map_or(0, |m| m.deposit) } else { 0 };
+            let released = released + if k == i { voter.deposit } else { 0 };
+            -(released as i128)
+        })
+        .collect();
+    let reserved_after = reserved_balances(api, &acc_ids, None).await?;
+    check_reserved("renouncing membership and removing votes", &acc_ids, &reserved_before, &reserved_after, &expected);
     Ok(())
 }
 
+/// Reserved balances of the given accounts at the given block, or at the best block if `None`.
+async fn reserved_balances(api: &OnlineClient<PolkadotConfig>, acc_ids: &[AccountId32], at: Option<H256>) -> Result<Vec<u128>, Box<dyn std::error::Error>> {
+    let mut reserved = Vec::new();
+    for acc_id in acc_ids.iter() {
+        let account = api.storage().fetch_or_default(&polkadot::storage().system().account(acc_id), at).await?;
+        reserved.push(account.data.reserved);
+    }
+    Ok(reserved)
+}
+
+/// Asserts that the reserved balance of each account changed by the expected amount during `step`.
+fn check_reserved(step: &str, acc_ids: &[AccountId32], before: &[u128], after: &[u128], expected: &[i128]) {
+    for (k, acc_id) in acc_ids.iter().enumerate() {
+        let change = after[k] as i128 - before[k] as i128;
+        if change != expected[k] {
+            panic!("ERROR: Reserved balance of account {} changed by {} after {}, expected {}", acc_id, change, step, expected[k]);
+        }
+    }
+}
+
+/// Waits for the next council election and compares its outcome with the one computed off-chain
+/// from the candidates and votes registered beforehand.
+/// Returns the hash of the block in which the election took place.
+pub async fn verify_election(api: &OnlineClient<PolkadotConfig>) -> Result<Option<H256>, Box<dyn std::error::Error>> {
+    let desired_members = api.constants().at(&polkadot::constants().phragmen_election().desired_members())?;
+    let term_duration = api.constants().at(&polkadot::constants().phragmen_election().term_duration())?;
+    // Candidates, sitting members and runners up all take part in the election.
+    let mut candidates: Vec<AccountId32> = api.storage().fetch_or_default(&polkadot::storage().phragmen_election().candidates(), None).await?
+        .into_iter().map(|(who, _)| who).collect();
+    let members = api.storage().fetch_or_default(&polkadot::storage().phragmen_election().members(), None).await?;
+    let runners_up = api.storage().fetch_or_default(&polkadot::storage().phragmen_election().runners_up(), None).await?;
+    candidates.extend(members.into_iter().map(|s| s.who));
+    candidates.extend(runners_up.into_iter().map(|s| s.who));
+    let mut voters = Vec::new();
+    let mut iter = api.storage().iter(polkadot::storage().phragmen_election().voting_root(), 10, None).await?;
+    while let Some((_, voter)) = iter.next().await? {
+        voters.push((voter.votes, voter.stake));
+    }
+    // Stakes are turned into votes the same way as `CurrencyToVote` does.
+    let total_issuance = api.storage().fetch_or_default(&polkadot::storage().balances().total_issuance(), None).await?;
+    let factor = max(total_issuance / u64::MAX as u128, 1);
+    let nb_seats = desired_members as usize + api.constants().at(&polkadot::constants().phragmen_election().desired_runners_up())? as usize;
+    let winners: Vec<AccountId32> = seq_phragmen(&candidates, &voters, factor, nb_seats).0
+        .into_iter()
+        .filter(|(_, backing)| *backing > 0.0)
+        .map(|(who, _)| who)
+        .collect();
+    let split_point = min(desired_members as usize, winners.len());
+    let mut expected_members = winners[..split_point].to_vec();
+    expected_members.sort();
+    let expected_runners_up: Vec<AccountId32> = winners[split_point..].iter().rev().cloned().collect();
+    // The prime is the member with the most votes, weighted by their position in each ballot.
+    let mut prime_votes: Vec<(AccountId32, u128)> = expected_members.iter().map(|m| (m.clone(), 0)).collect();
+    for (votes, stake) in voters.iter() {
+        for (position, who) in votes.iter().enumerate() {
+            if let Some(p) = prime_votes.iter_mut().find(|p| p.0 == *who) {
+                p.1 = p.1.saturating_add(stake.saturating_mul((PHRAGMEN_MAXIMUM_VOTE - position) as u128));
+            }
+        }
+    }
+    let expected_prime = prime_votes.into_iter().max_by_key(|p| p.1).map(|p| p.0);
+    let events = match blocks::wait_for_event::<polkadot::phragmen_election::events::NewTerm>(api, term_duration + 5).await? {
+        Some(events) => events,
+        None => {
+            println!("### No council election within a term ###");
+            return Ok(None);
+        }
+    };
+    let at = Some(events.block_hash());
+    let members: Vec<AccountId32> = api.storage().fetch_or_default(&polkadot::storage().phragmen_election().members(), at).await?
+        .into_iter().map(|s| s.who).collect();
+    let runners_up: Vec<AccountId32> = api.storage().fetch_or_default(&polkadot::storage().phragmen_election().runners_up(), at).await?
+        .into_iter().map(|s| s.who).collect();
+    let council_members = api.storage().fetch_or_default(&polkadot::storage().council().members(), at).await?;
+    let prime = api.storage().fetch(&polkadot::storage().council().prime(), at).await?;
+    println!("Council elected from {} candidates and {} voters: {} members, {} runners up",candidates.len(), voters.len(), members.len(), runners_up.len());
+    if members != expected_members {
+        println!("### Elected members do not match ###");
+        println!("Expected {:?}, elected {:?}.",expected_members, members);
+    }
+    if runners_up != expected_runners_up {
+        println!("### Runners up do not match ###");
+        println!("Expected {:?}, elected {:?}.",expected_runners_up, runners_up);
+    }
+    if council_members != expected_members {
+        println!("### Council members do not match the elected members ###");
+        println!("Expected {:?}, council {:?}.",expected_members, council_members);
+    }
+    if prime != expected_prime {
+        println!("### Council prime does not match ###");
+        println!("Expected {:?}, prime {:?}.",expected_prime, prime);
+    }
+    Ok(at)
+}
+
+/// Sequential Phragmen as run by elections-phragmen and the election provider miner, with the loads
+/// approximated by floats. Returns the winners in election order along with their backing, and for
+/// every voter the share of its stake going to each of the winners it voted for.
+pub fn seq_phragmen(candidates: &[AccountId32], voters: &[(Vec<AccountId32>, u128)], factor: u128, to_elect: usize) -> (Vec<(AccountId32, f64)>, Vec<Vec<(AccountId32, f64)>>) {
+    let nb_candidates = candidates.len();
+    // Votes for accounts which are not candidates are ignored.
+    let edges: Vec<(f64, Vec<usize>)> = voters.iter().map(|(votes, stake)| (
+        (stake / factor) as f64,
+        votes.iter().filter_map(|v| candidates.iter().position(|c| c == v)).collect(),
+    )).collect();
+    let mut approval = vec![0f64; nb_candidates];
+    for (stake, targets) in edges.iter() {
+        for t in targets.iter() {
+            approval[*t] += stake;
+        }
+    }
+    let mut elected = vec![false; nb_candidates];
+    let mut winners = Vec::new();
+    let mut voter_load = vec![0f64; edges.len()];
+    let mut edge_load: Vec<Vec<f64>> = edges.iter().map(|(_, targets)| vec![0f64; targets.len()]).collect();
+    for _ in 0..min(to_elect, nb_candidates) {
+        let mut score: Vec<f64> = approval.iter().map(|a| if 0.0 == *a { f64::INFINITY } else { 1.0 / a }).collect();
+        for (v, (stake, targets)) in edges.iter().enumerate() {
+            for t in targets.iter() {
+                if !elected[*t] && 0.0 != approval[*t] {
+                    score[*t] += voter_load[v] * stake / approval[*t];
+                }
+            }
+        }
+        // Ties go to the first candidate, as in the runtime.
+        let winner = (0..nb_candidates)
+            .filter(|c| !elected[*c])
+            .min_by(|a, b| score[*a].partial_cmp(&score[*b]).expect("scores are never NaN; qed"));
+        let w = match winner {
+            Some(w) => w,
+            None => break,
+        };
+        elected[w] = true;
+        winners.push(w);
+        for (v, (_, targets)) in edges.iter().enumerate() {
+            if let Some(e) = targets.iter().position(|t| *t == w) {
+                edge_load[v][e] = score[w] - voter_load[v];
+                voter_load[v] = score[w];
+            }
+        }
+    }
+    let mut backing = vec![0f64; nb_candidates];
+    let mut shares = Vec::new();
+    for (v, (stake, targets)) in edges.iter().enumerate() {
+        let mut voter_shares = Vec::new();
+        for (e, t) in targets.iter().enumerate() {
+            if elected[*t] && 0.0 < voter_load[v] {
+                backing[*t] += stake * edge_load[v][e] / voter_load[v];
+                voter_shares.push((candidates[*t].clone(), edge_load[v][e] / voter_load[v]));
+            }
+        }
+        shares.push(voter_shares);
+    }
+    (winners.into_iter().map(|w| (candidates[w].clone(), backing[w])).collect(), shares)
+}
 
-pub async fn external_majority_workflow(api: &OnlineClient<PolkadotConfig>, acc_seed_accounts : &[sr25519::Pair]) -> Result<(), Box<dyn std::error::Error>> {
+/// Routes the approval of a new treasury spend proposal through an external majority referendum.
+/// Returns the index of the treasury proposal.
+pub async fn external_majority_workflow(api: &OnlineClient<PolkadotConfig>, acc_seed_accounts : &[sr25519::Pair]) -> Result<u32, Box<dyn std::error::Error>> {
     let tx_params = Params::new()
         .tip(PlainTip::new(0))
         .era(Era::Immortal, api.genesis_hash());
@@ -104,6 +374,8 @@ pub async fn external_majority_workflow(api: &OnlineClient<PolkadotConfig>, acc_
     let treasury_proposal_storage_index = polkadot::storage().treasury().proposal_count();
     let treasury_proposal_index_before = api.storage().fetch(&treasury_proposal_storage_index, None).await?;
     let i = 10;
+    let acc_id: AccountId32 = acc_seed_accounts[i as usize].public().into();
+    let (_, reserved_before, ..) = balances::account_data(api, &acc_id, None).await?;
     let acc_signer = PairSigner::new(acc_seed_accounts[i as usize].clone());
     // submit the transaction:
     let hash = api.tx().sign_and_submit(&treasury_proposal_tx, &acc_signer, tx_params).await?;
@@ -119,17 +391,41 @@ pub async fn external_majority_workflow(api: &OnlineClient<PolkadotConfig>, acc_
     } else {
         panic!("ERROR: Treasury proposal incorrectly registered");
     };
+    // The proposer reserves the proposal bond.
+    let bond = treasury::proposal_bond(api, TEST_ACCOUNT_FUNDING)?;
+    let proposal = api.storage().fetch(&polkadot::storage().treasury().proposals(&treasury_proposal_index), None).await?
+        .expect("ERROR: Treasury proposal incorrectly registered");
+    assert_eq!(proposal.bond, bond);
+    let (_, reserved_after, ..) = balances::account_data(api, &acc_id, None).await?;
+    assert_eq!(reserved_after - reserved_before, bond);
     // Noting the preimage by account 10, may or may not be a councillor.
     let call = Call::Treasury(TreasuryCall::approve_proposal { proposal_id: treasury_proposal_index }).encode();
     let preimage_hash = BlakeTwo256::hash(&call[..]);
     let submit_preimage_tx = polkadot::tx().democracy().note_preimage(call);
     let hash = api.tx().sign_and_submit(&submit_preimage_tx, &acc_signer, tx_params).await?;
     println!("Note preimage extrinsic submitted for test account {}: {}",i, hash);
+    external_propose_majority(api, acc_seed_accounts, preimage_hash).await?;
+    tokio::time::sleep(Duration::from_secs(60+BLOCK_INCLUSION_LAG)).await;
+    Ok(treasury_proposal_index)
+}
+
+/// Has the council table `proposal_hash` as the next external majority proposal.
+pub async fn external_propose_majority(api: &OnlineClient<PolkadotConfig>, acc_seed_accounts : &[sr25519::Pair], proposal_hash: H256) -> Result<(), Box<dyn std::error::Error>> {
+    let tx_params = Params::new()
+        .tip(PlainTip::new(0))
+        .era(Era::Immortal, api.genesis_hash());
+    let councillors_addr = polkadot::storage().phragmen_election().members();
+    let councillors = api.storage().fetch(&councillors_addr, None).await?.unwrap();
+    if 0==councillors.len(){
+        panic!("The council has not been setup.");
+    }
     // Councillor 0 proposes
-    let call = Call::Democracy(DemocracyCall::external_propose_majority { proposal_hash: preimage_hash });
+    let call = Call::Democracy(DemocracyCall::external_propose_majority { proposal_hash });
     let call_hash = BlakeTwo256::hash(&call.encode()[..]);
-    let tx = polkadot::tx().council().propose(8, call, 42);
     let c0_pos = acc_seed_accounts.iter().position(|x|councillors[0].who == x.public().into());
+    let (length_bound, weight_bound) = call_bounds(api, &call, &acc_seed_accounts[c0_pos.unwrap() as usize]).await?;
+    let threshold = Majority::ThreeFifths.threshold(councillors.len() as u32);
+    let tx = polkadot::tx().council().propose(threshold, call, length_bound);
     let c0_signer = PairSigner::new(acc_seed_accounts[c0_pos.unwrap() as usize].clone());
     let hash = api.tx().sign_and_submit(&tx, &c0_signer, tx_params).await?;
     println!("External propose majority extrinsic submitted for councillor {}: {}",0, hash);
@@ -150,12 +446,211 @@ pub async fn external_majority_workflow(api: &OnlineClient<PolkadotConfig>, acc_
     let tx = polkadot::tx().council().close(
         call_hash,
         council_proposal_index,
-        WEIGHT_BOUND,
-        LENGTH_BOUND);
+        weight_bound,
+        length_bound);
     let c_pos = acc_seed_accounts.iter().position(|x|councillors[0].who == x.public().into());
     let c_signer = PairSigner::new(acc_seed_accounts[c_pos.unwrap() as usize].clone());
     let hash = api.tx().sign_and_submit(&tx, &c_signer, tx_params).await?;
     println!("Councillor close extrinsic submitted by councillor {:?}, account {:?}: {}",councillors[0].who, c_pos, hash);
-    tokio::time::sleep(Duration::from_secs(60+BLOCK_INCLUSION_LAG)).await;
+    Ok(())
+}
+
+/// Share of the council required for a motion to pass.
+#[derive(Clone, Copy)]
+pub enum Majority {
+    /// More than half of the council.
+    Simple,
+    /// At least three fifths of the council.
+    ThreeFifths,
+    /// The whole council.
+    Unanimous,
+}
+
+impl Majority {
+    /// Number of ayes required out of `members` seats.
+    pub fn threshold(&self, members: u32) -> u32 {
+        match self {
+            Majority::Simple => members / 2 + 1,
+            Majority::ThreeFifths => (3 * members + 4) / 5,
+            Majority::Unanimous => members,
+        }
+    }
+}
+
+/// Runtime call passed as is, so that it can be signed without being wrapped in a pallet call.
+struct RawCall(Vec<u8>);
+
+impl TxPayload for RawCall {
+    fn encode_call_data(&self, _metadata: &Metadata, out: &mut Vec<u8>) -> Result<(), subxt::Error> {
+        out.extend_from_slice(&self.0);
+        Ok(())
+    }
+}
+
+/// Encoded length and dispatch weight of a motion, as bounded by `council().propose` and `council().close`.
+/// The weight is queried from the transaction payment RPC for an extrinsic made of the call alone.
+pub async fn call_bounds(api: &OnlineClient<PolkadotConfig>, call: &Call, signer: &sr25519::Pair) -> Result<(u32, u64), Box<dyn std::error::Error>> {
+    let encoded = call.encode();
+    let length = encoded.len() as u32;
+    let extrinsic = api.tx().create_signed(&RawCall(encoded), &PairSigner::new(signer.clone()), Default::default()).await?;
+    let info: serde_json::Value = api
+        .rpc()
+        .client
+        .request("payment_queryInfo", rpc_params![format!("0x{}", hex::encode(extrinsic.encoded()))])
+        .await?;
+    let weight = info["weight"].as_u64().expect("the dispatch info has a weight; qed");
+    Ok((length, weight))
+}
+
+/// Harmless motion which the council can dispatch, distinct for every `k`.
+fn motion(k: usize) -> Call {
+    let calls = (0..k).map(|_| Call::Utility(UtilityCall::batch { calls: vec![] })).collect();
+    Call::Utility(UtilityCall::batch { calls })
+}
+
+/// Proposes `call` to the council and returns the motion hash and index.
+async fn propose_motion(api: &OnlineClient<PolkadotConfig>, proposer: &sr25519::Pair, call: Call, threshold: u32) -> Result<(H256, u32), Box<dyn std::error::Error>> {
+    let (length_bound, _) = call_bounds(api, &call, proposer).await?;
+    let tx = polkadot::tx().council().propose(threshold, call, length_bound);
+    let proposed = submit(api, proposer, &tx).await?
+        .find_first::<polkadot::council::events::Proposed>()?
+        .expect("ERROR: Council motion incorrectly registered");
+    assert_eq!(proposed.threshold, threshold);
+    println!("Council motion {} proposed with threshold {}: {:?}",proposed.proposal_index, threshold, proposed.proposal_hash);
+    Ok((proposed.proposal_hash, proposed.proposal_index))
+}
+
+/// Votes on a council motion from all the given councillors.
+async fn vote_motion(api: &OnlineClient<PolkadotConfig>, councillors: &[sr25519::Pair], proposal_hash: H256, index: u32, approve: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let tx_params = Params::new()
+        .tip(PlainTip::new(0))
+        .era(Era::Immortal, api.genesis_hash());
+    let tx = polkadot::tx().council().vote(proposal_hash, index, approve);
+    for c in councillors.iter() {
+        let hash = api.tx().sign_and_submit(&tx, &PairSigner::new(c.clone()), tx_params).await?;
+        println!("Councillor {} vote extrinsic submitted for motion {}: {}",if approve {"aye"} else {"nay"}, index, hash);
+    }
+    Ok(())
+}
+
+/// Closes a council motion with the exact bounds of the proposed call.
+async fn submit_close(api: &OnlineClient<PolkadotConfig>, closer: &sr25519::Pair, proposal_hash: H256, index: u32) -> Result<Result<TxEvents<PolkadotConfig>, subxt::Error>, Box<dyn std::error::Error>> {
+    let proposal = api.storage().fetch(&polkadot::storage().council().proposal_of(&proposal_hash), None).await?
+        .expect("ERROR: Council motion incorrectly registered");
+    let (length_bound, weight_bound) = call_bounds(api, &proposal, closer).await?;
+    let tx = polkadot::tx().council().close(proposal_hash, index, weight_bound, length_bound);
+    Ok(submit(api, closer, &tx).await)
+}
+
+/// Closes a council motion and checks the final tally and outcome.
+async fn close_motion(api: &OnlineClient<PolkadotConfig>, closer: &sr25519::Pair, proposal_hash: H256, index: u32, yes: u32, no: u32, approved: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let events = submit_close(api, closer, proposal_hash, index).await??;
+    let closed = events.find_first::<polkadot::council::events::Closed>()?
+        .expect("ERROR: Council motion not closed");
+    assert_eq!(closed.proposal_hash, proposal_hash);
+    assert_eq!((closed.yes, closed.no), (yes, no));
+    assert_eq!(events.has::<polkadot::council::events::Approved>()?, approved);
+    assert_eq!(events.has::<polkadot::council::events::Disapproved>()?, !approved);
+    match events.find_first::<polkadot::council::events::Executed>()? {
+        Some(executed) => {
+            assert!(approved);
+            assert!(executed.result.is_ok());
+        },
+        None => assert!(!approved),
+    }
+    let proposal_of = api.storage().fetch(&polkadot::storage().council().proposal_of(&proposal_hash), None).await?;
+    assert!(proposal_of.is_none());
+    println!("Council motion {} closed with {} ayes and {} nays, approved: {}",index, yes, no, approved);
+    Ok(())
+}
+
+/// Has the whole council pass a motion dispatching the encoded `call` and returns the events of its closing.
+/// The call is taken encoded so that it can be built from the runtime types of any module.
+pub async fn pass_motion(api: &OnlineClient<PolkadotConfig>, acc_seed_accounts : &[sr25519::Pair], call: &[u8]) -> Result<TxEvents<PolkadotConfig>, Box<dyn std::error::Error>> {
+    let call = Call::decode(&mut &call[..]).expect("the call was encoded from the same metadata; qed");
+    let members = api.storage().fetch_or_default(&polkadot::storage().council().members(), None).await?;
+    let councillors: Vec<sr25519::Pair> = members.iter()
+        .filter_map(|m| acc_seed_accounts.iter().find(|x| *m == x.public().into()).cloned())
+        .collect();
+    if councillors.len() != members.len() || members.len() < 2 {
+        panic!("ERROR: The council is not made of at least 2 test accounts");
+    }
+    let seats = members.len() as u32;
+    let (hash, index) = propose_motion(api, &councillors[0], call, Majority::Unanimous.threshold(seats)).await?;
+    vote_motion(api, &councillors, hash, index, true).await?;
+    tokio::time::sleep(Duration::from_secs(BLOCK_INCLUSION_LAG)).await;
+    let events = submit_close(api, &councillors[0], hash, index).await??;
+    match events.find_first::<polkadot::council::events::Executed>()? {
+        Some(executed) => assert!(executed.result.is_ok(), "ERROR: Council motion {} failed: {:?}", index, executed.result),
+        None => panic!("ERROR: Council motion {} not executed", index),
+    }
+    println!("Council motion {} passed and executed",index);
+    Ok(events)
+}
+
+/// Runs council motions through every outcome: approval, rejection, duplicates, root disapproval
+/// and closing after the motion duration with the prime's default vote.
+pub async fn motion_lifecycle(api: &OnlineClient<PolkadotConfig>, acc_seed_accounts : &[sr25519::Pair], sudo_seed_account: &sr25519::Pair) -> Result<(), Box<dyn std::error::Error>> {
+    let members = api.storage().fetch_or_default(&polkadot::storage().council().members(), None).await?;
+    let prime = api.storage().fetch(&polkadot::storage().council().prime(), None).await?;
+    let councillors: Vec<sr25519::Pair> = members.iter()
+        .filter_map(|m| acc_seed_accounts.iter().find(|x| *m == x.public().into()).cloned())
+        .collect();
+    if councillors.len() != members.len() || members.len() < 3 {
+        println!("### The council is not made of at least 3 test accounts, skipping the motions ###");
+        return Ok(());
+    }
+    let seats = members.len() as u32;
+    let threshold = Majority::Simple.threshold(seats);
+    // A motion backed by all the councillors is approved and executed.
+    let (hash, index) = propose_motion(api, &councillors[0], motion(1), threshold).await?;
+    vote_motion(api, &councillors, hash, index, true).await?;
+    tokio::time::sleep(Duration::from_secs(BLOCK_INCLUSION_LAG)).await;
+    close_motion(api, &councillors[0], hash, index, seats, 0, true).await?;
+    // A motion which can no longer reach the threshold is disapproved right away.
+    let (hash, index) = propose_motion(api, &councillors[0], motion(2), threshold).await?;
+    let nb_nays = seats - threshold + 1;
+    vote_motion(api, &councillors[..nb_nays as usize], hash, index, false).await?;
+    tokio::time::sleep(Duration::from_secs(BLOCK_INCLUSION_LAG)).await;
+    close_motion(api, &councillors[0], hash, index, 0, nb_nays, false).await?;
+    // The same motion cannot be proposed twice, and root can disapprove it.
+    let (hash, _) = propose_motion(api, &councillors[0], motion(3), threshold).await?;
+    let (length_bound, _) = call_bounds(api, &motion(3), &councillors[1]).await?;
+    let tx = polkadot::tx().council().propose(threshold, motion(3), length_bound);
+    errors::assert_module_error(api, submit(api, &councillors[1], &tx).await, "DuplicateProposal");
+    let events = sudo(api, sudo_seed_account, Call::Council(CouncilCall::disapprove_proposal { proposal_hash: hash })).await?;
+    let disapproved = events.find_first::<polkadot::council::events::Disapproved>()?
+        .expect("ERROR: Council motion not disapproved by root");
+    assert_eq!(disapproved.proposal_hash, hash);
+    let proposal_of = api.storage().fetch(&polkadot::storage().council().proposal_of(&hash), None).await?;
+    assert!(proposal_of.is_none());
+    println!("Council motion disapproved by root: {:?}",hash);
+    // Unanimous motions stay open until the motion duration expires.
+    // Abstentions then follow the prime's vote.
+    let prime_pos = prime.and_then(|p| members.iter().position(|m| *m == p));
+    let other_pos = if Some(0) == prime_pos { 1 } else { 0 };
+    let (hash_prime, index_prime) = propose_motion(api, &councillors[0], motion(4), Majority::Unanimous.threshold(seats)).await?;
+    let (hash_other, index_other) = propose_motion(api, &councillors[0], motion(5), Majority::Unanimous.threshold(seats)).await?;
+    if let Some(p) = prime_pos {
+        vote_motion(api, &councillors[p..p + 1], hash_prime, index_prime, true).await?;
+    }
+    vote_motion(api, &councillors[other_pos..other_pos + 1], hash_other, index_other, true).await?;
+    tokio::time::sleep(Duration::from_secs(BLOCK_INCLUSION_LAG)).await;
+    let result = submit_close(api, &councillors[0], hash_other, index_other).await?;
+    errors::assert_module_error(api, result, "TooEarly");
+    let voting = api.storage().fetch(&polkadot::storage().council().voting(&hash_other), None).await?
+        .expect("ERROR: Council motion votes incorrectly registered");
+    let now = blocks::block_number(api, None).await?;
+    if voting.end > now + MAX_MOTION_DURATION {
+        println!("### Council motions end at block {}, too late to wait for them ###",voting.end);
+        return Ok(());
+    }
+    if voting.end >= now {
+        blocks::wait_blocks(api, voting.end - now + 1).await?;
+    }
+    if prime_pos.is_some() {
+        close_motion(api, &councillors[0], hash_prime, index_prime, seats, 0, true).await?;
+    }
+    close_motion(api, &councillors[0], hash_other, index_other, 1, seats - 1, false).await?;
     Ok(())
 }
\ No newline at end of file
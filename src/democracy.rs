@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::time::Duration;
 use codec::Encode;
 use subxt::{
@@ -8,14 +9,19 @@ use subxt::{
         PolkadotExtrinsicParamsBuilder as Params,
     },
     ext::{
-        sp_core::sr25519,
-        sp_runtime::traits::{BlakeTwo256, Hash},
+        sp_core::{sr25519, Pair, H256},
+        sp_runtime::{
+            AccountId32,
+            traits::{BlakeTwo256, Hash}
+        },
     },
     OnlineClient,
     PolkadotConfig,
 };
 use rand::Rng;
 use crate::consts::*;
+use crate::{balances, blocks, council};
+use crate::tx::submit;
 
 #[subxt::subxt(runtime_metadata_path = "./data/metadata.scale")]
 pub mod polkadot {}
@@ -23,6 +29,7 @@ use polkadot::runtime_types::pallet_democracy::vote::Vote;
 
 type Call = polkadot::runtime_types::edgeware_runtime::Call;
 type SystemCall = polkadot::runtime_types::frame_system::pallet::Call;
+type ReferendumInfo = polkadot::runtime_types::pallet_democracy::types::ReferendumInfo<u32, H256, u128>;
 
 type AccountVote = polkadot::runtime_types::pallet_democracy::vote::AccountVote<::core::primitive::u128>;
 
@@ -130,4 +137,125 @@ pub async fn vote(api: &OnlineClient<PolkadotConfig>, acc_seed_accounts : &[sr25
     tokio::time::sleep(Duration::from_secs(BLOCK_INCLUSION_LAG)).await;
     tokio::time::sleep(Duration::from_secs(2 * 60)).await;
     Ok(())
+}
+
+/// Submits public proposals with different deposits and seconds, then follows the launch periods
+/// until all of them have been tabled, checking the queue ordering and the deposits refund.
+pub async fn public_proposal_queue(api: &OnlineClient<PolkadotConfig>, acc_seed_accounts : &[sr25519::Pair]) -> Result<(), Box<dyn std::error::Error>> {
+    let tx_params = Params::new()
+        .tip(PlainTip::new(0))
+        .era(Era::Immortal, api.genesis_hash());
+    let minimum_deposit = api.constants().at(&polkadot::constants().democracy().minimum_deposit())?;
+    let launch_period = api.constants().at(&polkadot::constants().democracy().launch_period())?;
+    // Users 30.. submit the proposals, users 40.. second them.
+    let mut seconder = 40;
+    let mut proposals = Vec::new();
+    let mut reserved_before = HashMap::new();
+    for k in 0..NB_PUBLIC_PROPOSALS {
+        let i = 30 + k as usize;
+        let call = Call::System(SystemCall::remark {
+            remark: format!("Public proposal {}", k).into_bytes()
+        }).encode();
+        let proposal_hash = BlakeTwo256::hash(&call[..]);
+        let deposit = minimum_deposit * (k + 1) as u128;
+        let acc_id: AccountId32 = acc_seed_accounts[i].public().into();
+        reserved_before.insert(acc_id.clone(), balances::account_data(api, &acc_id, None).await?.1);
+        let tx = polkadot::tx().democracy().propose(proposal_hash, deposit);
+        let proposed = submit(api, &acc_seed_accounts[i], &tx).await?
+            .find_first::<polkadot::democracy::events::Proposed>()?
+            .expect("ERROR: Democracy proposal incorrectly registered");
+        assert_eq!(proposed.deposit, deposit);
+        println!("Democracy proposal {} submitted for test account {} with deposit {}",proposed.proposal_index, i, deposit);
+        // The lower the deposit, the more seconds, so that the backing order differs from the deposit order.
+        let nb_seconds = 2 * (NB_PUBLIC_PROPOSALS - 1 - k);
+        let tx = polkadot::tx().democracy().second(proposed.proposal_index, NB_TEST_ACCOUNTS);
+        for _ in 0..nb_seconds {
+            let acc_id: AccountId32 = acc_seed_accounts[seconder].public().into();
+            reserved_before.insert(acc_id.clone(), balances::account_data(api, &acc_id, None).await?.1);
+            let acc_signer = PairSigner::new(acc_seed_accounts[seconder].clone());
+            // submit the transaction:
+            let hash = api.tx().sign_and_submit(&tx, &acc_signer, tx_params).await?;
+            println!("Second extrinsic submitted for test account {}: {}",seconder, hash);
+            seconder += 1;
+        }
+        proposals.push((proposed.proposal_index, deposit, nb_seconds));
+    }
+    tokio::time::sleep(Duration::from_secs(BLOCK_INCLUSION_LAG)).await;
+    // Every depositor reserves the proposal deposit.
+    for (index, deposit, nb_seconds) in proposals.iter() {
+        let deposit_of_addr = polkadot::storage().democracy().deposit_of(index);
+        let (depositors, d) = api.storage().fetch(&deposit_of_addr, None).await?
+            .expect("ERROR: Democracy proposal deposit incorrectly registered");
+        assert_eq!(d, *deposit);
+        assert_eq!(depositors.len() as u32, nb_seconds + 1);
+        for depositor in depositors.iter() {
+            let reserved = balances::account_data(api, depositor, None).await?.1;
+            assert_eq!(reserved as i128 - reserved_before[depositor] as i128, *deposit as i128);
+        }
+    }
+    // The council queues an external proposal so that both queues compete for the launch periods.
+    let block = blocks::block_number(api, None).await?;
+    let call = Call::System(SystemCall::remark {
+        remark: format!("External proposal at block {}", block).into_bytes()
+    }).encode();
+    council::external_propose_majority(api, acc_seed_accounts, BlakeTwo256::hash(&call[..])).await?;
+    tokio::time::sleep(Duration::from_secs(BLOCK_INCLUSION_LAG)).await;
+    // Follow the launch periods until all the proposals have been tabled.
+    let mut pending: Vec<u32> = proposals.iter().map(|p| p.0).collect();
+    let mut launches = 0;
+    while !pending.is_empty() {
+        launches += 1;
+        if launches > 2 * NB_PUBLIC_PROPOSALS + 2 {
+            panic!("ERROR: Democracy proposals {:?} have not been tabled", pending);
+        }
+        let public_props = api.storage().fetch_or_default(&polkadot::storage().democracy().public_props(), None).await?;
+        let next_external = api.storage().fetch(&polkadot::storage().democracy().next_external(), None).await?;
+        let last_tabled_was_external = api.storage().fetch_or_default(&polkadot::storage().democracy().last_tabled_was_external(), None).await?;
+        // The runtime launches the last of the most backed public proposals.
+        let mut expected_public = None;
+        let mut best_backing = 0;
+        for (index, _, _) in public_props.iter() {
+            let deposit_of_addr = polkadot::storage().democracy().deposit_of(index);
+            let (depositors, deposit) = api.storage().fetch(&deposit_of_addr, None).await?.unwrap_or_default();
+            let backing = deposit * depositors.len() as u128;
+            if expected_public.is_none() || backing >= best_backing {
+                expected_public = Some(*index);
+                best_backing = backing;
+            }
+        }
+        // External and public proposals alternate when both queues are populated.
+        let expect_external = if last_tabled_was_external { public_props.is_empty() } else { next_external.is_some() };
+        let events = blocks::wait_for_event::<polkadot::democracy::events::Started>(api, 2 * launch_period).await?
+            .expect("ERROR: No referendum started during the launch period");
+        let started = events.find_first::<polkadot::democracy::events::Started>()?.unwrap();
+        let external_tabled = events.has::<polkadot::democracy::events::ExternalTabled>()?;
+        assert_eq!(external_tabled, expect_external);
+        if external_tabled {
+            println!("External proposal tabled as referendum {}",started.ref_index);
+            continue;
+        }
+        let tabled = events.find_first::<polkadot::democracy::events::Tabled>()?
+            .expect("ERROR: Public proposal incorrectly tabled");
+        assert_eq!(Some(tabled.proposal_index), expected_public);
+        println!("Public proposal {} tabled as referendum {} with backing {}",tabled.proposal_index, started.ref_index, best_backing);
+        let referendum_addr = polkadot::storage().democracy().referendum_info_of(&started.ref_index);
+        let proposal_hash = public_props.iter().find(|p| p.0 == tabled.proposal_index).unwrap().1;
+        match api.storage().fetch(&referendum_addr, None).await? {
+            Some(ReferendumInfo::Ongoing(status)) => assert_eq!(status.proposal_hash, proposal_hash),
+            _ => panic!("ERROR: Referendum {} incorrectly started", started.ref_index),
+        }
+        if let Some((_, deposit, nb_seconds)) = proposals.iter().find(|p| p.0 == tabled.proposal_index) {
+            assert_eq!(tabled.deposit, *deposit);
+            assert_eq!(tabled.depositors.len() as u32, nb_seconds + 1);
+            pending.retain(|index| *index != tabled.proposal_index);
+        }
+    }
+    // Tabling a proposal returns the deposits of the proposer and the seconders.
+    for (acc_id, reserved) in reserved_before.iter() {
+        let reserved_after = balances::account_data(api, acc_id, None).await?.1;
+        if reserved_after != *reserved {
+            panic!("ERROR: Democracy deposit of account {} not returned: reserved before/after {} / {}", acc_id, reserved, reserved_after);
+        }
+    }
+    Ok(())
 }
\ No newline at end of file
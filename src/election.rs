@@ -0,0 +1,454 @@
+use std::cmp::{max, min};
+use std::collections::HashMap;
+use codec::{Compact, Decode, Encode};
+use futures::StreamExt;
+use subxt::{
+    ext::{
+        sp_core::{sr25519, Pair, H256},
+        sp_runtime::AccountId32,
+    },
+    OnlineClient,
+    PolkadotConfig,
+};
+use crate::consts::*;
+use crate::{balances, blocks, council, errors};
+use crate::tx::submit;
+
+#[subxt::subxt(runtime_metadata_path = "./data/metadata.scale")]
+pub mod polkadot {}
+
+type Phase = polkadot::runtime_types::pallet_election_provider_multi_phase::Phase<u32>;
+type ReadySolution = polkadot::runtime_types::pallet_election_provider_multi_phase::ReadySolution<AccountId32>;
+type RawSolution = polkadot::runtime_types::pallet_election_provider_multi_phase::RawSolution<NposSolution16>;
+type NposSolution16 = polkadot::runtime_types::edgeware_runtime::NposSolution16;
+type ElectionScore = polkadot::runtime_types::sp_npos_elections::ElectionScore;
+
+/// Factor turning stakes into votes, as `CurrencyToVote` does.
+pub async fn vote_factor(api: &OnlineClient<PolkadotConfig>, at: Option<H256>) -> Result<u128, Box<dyn std::error::Error>> {
+    let total_issuance = api.storage().fetch_or_default(&polkadot::storage().balances().total_issuance(), at).await?;
+    Ok(max(total_issuance / u64::MAX as u128, 1))
+}
+
+/// Upper bound of the bag holding voters with the given score.
+pub fn bag_upper(thresholds: &[u64], score: u64) -> u64 {
+    thresholds.iter().find(|t| **t >= score).cloned().unwrap_or(u64::MAX)
+}
+
+/// Checks the score claimed by a solution against its supports.
+fn check_score(solution: &ReadySolution) {
+    let totals: Vec<u128> = solution.supports.iter().map(|(_, s)| s.total).collect();
+    let minimal_stake = totals.iter().min().cloned().unwrap_or(0);
+    let sum_stake = totals.iter().fold(0u128, |acc, t| acc.saturating_add(*t));
+    let sum_stake_squared = totals.iter().fold(0u128, |acc, t| acc.saturating_add(t.saturating_mul(*t)));
+    assert_eq!(solution.score.minimal_stake, minimal_stake);
+    assert_eq!(solution.score.sum_stake, sum_stake);
+    assert_eq!(solution.score.sum_stake_squared, sum_stake_squared);
+    for (_, support) in solution.supports.iter() {
+        assert_eq!(support.total, support.voters.iter().map(|(_, v)| v).sum::<u128>());
+    }
+}
+
+/// Checks the election snapshot against its metadata, the limits of the runtime and the nominations
+/// of the test accounts.
+async fn check_snapshot(api: &OnlineClient<PolkadotConfig>, acc_seed_accounts : &[sr25519::Pair], hash: H256) -> Result<(), Box<dyn std::error::Error>> {
+    let snapshot = api.storage().fetch(&polkadot::storage().election_provider_multi_phase().snapshot(), Some(hash)).await?
+        .expect("ERROR: No election snapshot");
+    let metadata = api.storage().fetch(&polkadot::storage().election_provider_multi_phase().snapshot_metadata(), Some(hash)).await?
+        .expect("ERROR: No election snapshot metadata");
+    let desired_targets = api.storage().fetch(&polkadot::storage().election_provider_multi_phase().desired_targets(), Some(hash)).await?
+        .expect("ERROR: No desired targets");
+    let max_voters = api.constants().at(&polkadot::constants().election_provider_multi_phase().max_electing_voters())?;
+    let max_targets = api.constants().at(&polkadot::constants().election_provider_multi_phase().max_electable_targets())?;
+    assert_eq!((snapshot.voters.len() as u32, snapshot.targets.len() as u32), (metadata.voters, metadata.targets));
+    assert!(snapshot.voters.len() <= max_voters as usize);
+    assert!(snapshot.targets.len() <= max_targets as usize);
+    assert!(desired_targets as usize <= snapshot.targets.len());
+    // The test accounts vote with their active stake for the validators they nominate.
+    let factor = vote_factor(api, Some(hash)).await?;
+    let mut nb_nominators = 0;
+    for (i, acc) in acc_seed_accounts.iter().enumerate() {
+        let acc_id: AccountId32 = acc.public().into();
+        let nominations = match api.storage().fetch(&polkadot::storage().staking().nominators(&acc_id), Some(hash)).await? {
+            Some(nominations) => nominations,
+            None => continue,
+        };
+        let controller = api.storage().fetch(&polkadot::storage().staking().bonded(&acc_id), Some(hash)).await?
+            .unwrap_or_else(|| panic!("ERROR: Test account {} nominating but not bonded", i));
+        let ledger = api.storage().fetch(&polkadot::storage().staking().ledger(&controller), Some(hash)).await?
+            .unwrap_or_else(|| panic!("ERROR: No staking ledger for test account {}", i));
+        match snapshot.voters.iter().find(|(who, _, _)| *who == acc_id) {
+            Some((_, weight, targets)) => {
+                assert_eq!(*weight as u128, ledger.active / factor);
+                assert_eq!(targets.0, nominations.targets.0);
+            },
+            None if snapshot.voters.len() == max_voters as usize => {},
+            None => panic!("ERROR: Test account {} missing from the election snapshot", i),
+        }
+        nb_nominators += 1;
+    }
+    println!("Election snapshot: {} voters including {} test accounts, {} targets, {} desired",snapshot.voters.len(), nb_nominators, snapshot.targets.len(), desired_targets);
+    Ok(())
+}
+
+/// Follows the election provider through its signed and unsigned phases up to the next election,
+/// checking the snapshot, the queued solution and the finalized election.
+pub async fn verify_election_phases(api: &OnlineClient<PolkadotConfig>, acc_seed_accounts : &[sr25519::Pair]) -> Result<(), Box<dyn std::error::Error>> {
+    let signed_phase = api.constants().at(&polkadot::constants().election_provider_multi_phase().signed_phase())?;
+    let unsigned_phase = api.constants().at(&polkadot::constants().election_provider_multi_phase().unsigned_phase())?;
+    let mut sub = api.events().subscribe_finalized().await?;
+    let mut signed_start = None;
+    let mut unsigned_start = None;
+    let mut snapshot_checked = false;
+    let mut queued = None;
+    for _ in 0..MAX_ERA_DURATION {
+        let events = match sub.next().await {
+            Some(events) => events?,
+            None => break,
+        };
+        let hash = events.block_hash();
+        let number = blocks::block_number(api, Some(hash)).await?;
+        let phase = api.storage().fetch_or_default(&polkadot::storage().election_provider_multi_phase().current_phase(), Some(hash)).await?;
+        let round = api.storage().fetch_or_default(&polkadot::storage().election_provider_multi_phase().round(), Some(hash)).await?;
+        if let Some(started) = events.find_first::<polkadot::election_provider_multi_phase::events::SignedPhaseStarted>()? {
+            assert!(matches!(phase, Phase::Signed));
+            assert_eq!(started.round, round);
+            signed_start = Some(number);
+            println!("Signed phase of election round {} started at block {}",round, number);
+        }
+        if let Some(started) = events.find_first::<polkadot::election_provider_multi_phase::events::UnsignedPhaseStarted>()? {
+            assert!(matches!(phase, Phase::Unsigned((true, start)) if start == number));
+            assert_eq!(started.round, round);
+            if let Some(signed_start) = signed_start {
+                assert_eq!(number - signed_start, signed_phase);
+            }
+            unsigned_start = Some(number);
+            println!("Unsigned phase of election round {} started at block {}",round, number);
+        }
+        if !snapshot_checked && matches!(phase, Phase::Signed | Phase::Unsigned(_)) {
+            check_snapshot(api, acc_seed_accounts, hash).await?;
+            snapshot_checked = true;
+        }
+        if let Some(finalized) = events.find_first::<polkadot::election_provider_multi_phase::events::ElectionFinalized>()? {
+            // The queued solution, if any, is the one elected.
+            let compute = finalized.election_compute.expect("ERROR: Election failed");
+            match queued {
+                Some(queued) => assert_eq!(compute.encode(), queued),
+                None => println!("### Election of round {} finalized without a queued solution: {:?} ###",round - 1, compute),
+            }
+            assert!(matches!(phase, Phase::Off));
+            let snapshot = api.storage().fetch(&polkadot::storage().election_provider_multi_phase().snapshot(), Some(hash)).await?;
+            assert!(snapshot.is_none());
+            let solution = api.storage().fetch(&polkadot::storage().election_provider_multi_phase().queued_solution(), Some(hash)).await?;
+            assert!(solution.is_none());
+            if let Some(unsigned_start) = unsigned_start {
+                if number - unsigned_start != unsigned_phase {
+                    println!("### Unsigned phase lasted {} blocks instead of {} ###",number - unsigned_start, unsigned_phase);
+                }
+            }
+            println!("Election round {} finalized at block {} with {:?}",round - 1, number, compute);
+            return Ok(());
+        }
+        if let Some(solution) = api.storage().fetch(&polkadot::storage().election_provider_multi_phase().queued_solution(), Some(hash)).await? {
+            check_score(&solution);
+            if queued.is_none() {
+                println!("Solution queued at block {}: {:?}, score {:?}",number, solution.compute, solution.score);
+            }
+            queued = Some(solution.compute.encode());
+        }
+    }
+    panic!("ERROR: No election within {} blocks", MAX_ERA_DURATION);
+}
+
+/// Bag of a voter, given by its stash, checking that it matches its active stake.
+async fn check_bag(api: &OnlineClient<PolkadotConfig>, acc_id: &AccountId32, hash: H256) -> Result<u64, Box<dyn std::error::Error>> {
+    let thresholds = api.constants().at(&polkadot::constants().bags_list().bag_thresholds())?;
+    let controller = api.storage().fetch(&polkadot::storage().staking().bonded(acc_id), Some(hash)).await?
+        .expect("ERROR: Voter not bonded");
+    let ledger = api.storage().fetch(&polkadot::storage().staking().ledger(&controller), Some(hash)).await?
+        .expect("ERROR: No staking ledger");
+    let score = (ledger.active / vote_factor(api, Some(hash)).await?) as u64;
+    let node = api.storage().fetch(&polkadot::storage().bags_list().list_nodes(acc_id), Some(hash)).await?
+        .expect("ERROR: Voter not in the bags list");
+    assert_eq!(node.bag_upper, bag_upper(&thresholds, score));
+    Ok(node.bag_upper)
+}
+
+/// Checks that nominators are moved between bags of the voter list as their stake changes,
+/// and reordered within their bag with `put_in_front_of`.
+pub async fn verify_voter_bags(api: &OnlineClient<PolkadotConfig>, acc_seed_accounts : &[sr25519::Pair]) -> Result<(), Box<dyn std::error::Error>> {
+    let (voter, lighter, heavier) = (&acc_seed_accounts[246], &acc_seed_accounts[247], &acc_seed_accounts[248]);
+    let voter_id: AccountId32 = voter.public().into();
+    let lighter_id: AccountId32 = lighter.public().into();
+    let heavier_id: AccountId32 = heavier.public().into();
+    let ledger = match api.storage().fetch(&polkadot::storage().staking().ledger(&voter_id), None).await? {
+        Some(ledger) => ledger,
+        None => {
+            println!("### Test account 246 is not bonded, skipping the voter bags ###");
+            return Ok(());
+        },
+    };
+    let hash = api.rpc().finalized_head().await?;
+    let bag = check_bag(api, &voter_id, hash).await?;
+    // Unbonding most of the stake moves the voter to a lower bag.
+    let events = submit(api, voter, &polkadot::tx().staking().unbond(ledger.active * 9 / 10)).await?;
+    let lower_bag = check_bag(api, &voter_id, events.block_hash()).await?;
+    match events.find_first::<polkadot::bags_list::events::Rebagged>()? {
+        Some(rebagged) => assert_eq!((rebagged.who, rebagged.from, rebagged.to), (voter_id.clone(), bag, lower_bag)),
+        None => assert_eq!(lower_bag, bag),
+    }
+    println!("Voter {} moved from bag {} to bag {} by unbonding",voter_id, bag, lower_bag);
+    if lower_bag != bag {
+        let tx = polkadot::tx().bags_list().put_in_front_of(voter_id.clone());
+        errors::assert_module_error(api, submit(api, heavier, &tx).await, "NotInSameBag");
+    }
+    // Rebonding moves it back, after which an explicit rebag has nothing to do.
+    let events = submit(api, voter, &polkadot::tx().staking().rebond(ledger.active * 9 / 10)).await?;
+    assert_eq!(check_bag(api, &voter_id, events.block_hash()).await?, bag);
+    let events = submit(api, lighter, &polkadot::tx().bags_list().rebag(voter_id.clone())).await?;
+    assert!(!events.has::<polkadot::bags_list::events::Rebagged>()?);
+    // Within a bag, a heavier voter can move in front of a lighter one, but not the other way round.
+    let hash = api.rpc().finalized_head().await?;
+    let (lighter_bag, heavier_bag) = (check_bag(api, &lighter_id, hash).await?, check_bag(api, &heavier_id, hash).await?);
+    if lighter_bag != heavier_bag {
+        println!("### Test accounts 247 and 248 are in different bags, skipping the reordering ###");
+        return Ok(());
+    }
+    let tx = polkadot::tx().bags_list().put_in_front_of(heavier_id.clone());
+    errors::assert_module_error(api, submit(api, lighter, &tx).await, "NotHeavier");
+    let events = submit(api, heavier, &polkadot::tx().bags_list().put_in_front_of(lighter_id.clone())).await?;
+    let node = api.storage().fetch(&polkadot::storage().bags_list().list_nodes(&heavier_id), Some(events.block_hash())).await?
+        .expect("ERROR: Voter not in the bags list");
+    assert_eq!(node.next, Some(lighter_id.clone()));
+    let node = api.storage().fetch(&polkadot::storage().bags_list().list_nodes(&lighter_id), Some(events.block_hash())).await?
+        .expect("ERROR: Voter not in the bags list");
+    assert_eq!(node.prev, Some(heavier_id.clone()));
+    println!("Voter {} put in front of {} in bag {}",heavier_id, lighter_id, heavier_bag);
+    Ok(())
+}
+
+/// `parts` 65535ths of `value`, rounded as the runtime's `PerU16` multiplication does.
+fn per_u16_of(parts: u16, value: u128) -> u128 {
+    let (parts, accuracy) = (parts as u128, u16::MAX as u128);
+    value / accuracy * parts + (value % accuracy * parts + accuracy / 2) / accuracy
+}
+
+/// Adjusts `values` so that they add up to `target`, as `sp_arithmetic::normalize` does: the
+/// smallest values are bumped first and the largest ones decreased first.
+fn normalize(values: &[u128], target: u128) -> Vec<u128> {
+    let sum: u128 = values.iter().sum();
+    let count = values.len() as u128;
+    if values.is_empty() || sum == target {
+        return values.to_vec();
+    }
+    let diff = max(sum, target) - min(sum, target);
+    let per_round = diff / count;
+    let mut leftover = diff % count;
+    let mut sorted: Vec<(usize, u128)> = values.iter().cloned().enumerate().collect();
+    sorted.sort_by_key(|x| x.1);
+    let last = sorted.len() - 1;
+    if target > sum {
+        let (mut i, threshold) = (0, target / count);
+        if per_round > 0 {
+            for _ in 0..count {
+                sorted[i].1 += per_round;
+                if sorted[i].1 >= threshold {
+                    i = (i + 1) % sorted.len();
+                }
+            }
+        }
+        while leftover > 0 {
+            sorted[i].1 += 1;
+            if sorted[i].1 >= threshold {
+                i = (i + 1) % sorted.len();
+            }
+            leftover -= 1;
+        }
+    } else {
+        let (mut i, threshold) = (last, sorted[0].1);
+        if per_round > 0 {
+            for _ in 0..count {
+                if sorted[i].1 < per_round {
+                    leftover += per_round - sorted[i].1;
+                }
+                sorted[i].1 = sorted[i].1.saturating_sub(per_round);
+                if sorted[i].1 <= threshold {
+                    i = i.checked_sub(1).unwrap_or(last);
+                }
+            }
+        }
+        while leftover > 0 {
+            if sorted[i].1 > 0 {
+                sorted[i].1 -= 1;
+                leftover -= 1;
+            }
+            if sorted[i].1 <= threshold {
+                i = i.checked_sub(1).unwrap_or(last);
+            }
+        }
+    }
+    sorted.sort_by_key(|x| x.0);
+    sorted.into_iter().map(|(_, v)| v).collect()
+}
+
+/// Sequential Phragmen solution over the snapshot, as computed by the miner: every voter splits its
+/// weight between the winners it voted for in proportion to its Phragmen loads, expressed in
+/// `PerU16` parts. Returns the `(voter, [(target, parts)])` snapshot indices, the share of the last
+/// target being what the others leave, along with the score the runtime computes from the staked
+/// assignments, or `None` if fewer than `desired_targets` targets are backed.
+fn phragmen_solution(voters: &[(Vec<AccountId32>, u128)], targets: &[AccountId32], desired_targets: usize) -> Option<(Vec<(u32, Vec<(u16, u16)>)>, ElectionScore)> {
+    let (winners, shares) = council::seq_phragmen(targets, voters, 1, desired_targets);
+    if winners.len() < desired_targets || winners.iter().any(|(_, backing)| *backing <= 0.0) {
+        return None;
+    }
+    let target_index: HashMap<&AccountId32, u16> = targets.iter().enumerate().map(|(i, t)| (t, i as u16)).collect();
+    let mut assignments = Vec::new();
+    let mut supports: HashMap<u16, u128> = HashMap::new();
+    for (v, ((_, weight), voter_shares)) in voters.iter().zip(shares.into_iter()).enumerate() {
+        if voter_shares.is_empty() {
+            continue;
+        }
+        // The biggest share goes last and takes what the others leave, none of them being zero
+        // since the runtime drops such edges.
+        let mut distribution: Vec<(u16, f64)> = voter_shares.iter().map(|(t, share)| (target_index[t], *share)).collect();
+        distribution.sort_by(|a, b| a.1.partial_cmp(&b.1).expect("shares are never NaN; qed"));
+        let mut distribution: Vec<(u16, u16)> = distribution.into_iter()
+            .map(|(t, share)| (t, max((share * u16::MAX as f64) as u16, 1)))
+            .collect();
+        let (last, others) = distribution.split_last_mut().expect("the voter backs a winner; qed");
+        let taken: u32 = others.iter().map(|(_, parts)| *parts as u32).sum();
+        assert!(taken < u16::MAX as u32, "ERROR: Phragmen shares of voter {} exceed its stake", v);
+        last.1 = u16::MAX - taken as u16;
+        let staked: Vec<u128> = distribution.iter().map(|(_, parts)| per_u16_of(*parts, *weight)).collect();
+        for ((t, _), stake) in distribution.iter().zip(normalize(&staked, *weight)) {
+            *supports.entry(*t).or_insert(0) += stake;
+        }
+        assignments.push((v as u32, distribution));
+    }
+    let totals: Vec<u128> = supports.values().cloned().collect();
+    let score = ElectionScore {
+        minimal_stake: totals.iter().min().cloned().unwrap_or(0),
+        sum_stake: totals.iter().fold(0u128, |acc, t| acc.saturating_add(*t)),
+        sum_stake_squared: totals.iter().fold(0u128, |acc, t| acc.saturating_add(t.saturating_mul(*t))),
+    };
+    Some((assignments, score))
+}
+
+/// Compact solution of the given assignments, grouped by their number of targets: the parts of the
+/// last target of each assignment are implied.
+fn encode_solution(assignments: &[(u32, Vec<(u16, u16)>)]) -> NposSolution16 {
+    assert!(assignments.iter().all(|(_, d)| d.len() <= 16), "ERROR: Voter with more than 16 targets");
+    let mut encoded = Vec::new();
+    for nb_targets in 1..=16 {
+        let votes: Vec<&(u32, Vec<(u16, u16)>)> = assignments.iter().filter(|(_, d)| d.len() == nb_targets).collect();
+        encoded.extend(Compact(votes.len() as u32).encode());
+        for (voter, distribution) in votes {
+            encoded.extend(Compact(*voter).encode());
+            let (last, others) = distribution.split_last().expect("assignments are not empty; qed");
+            for (target, parts) in others.iter() {
+                encoded.extend(Compact(*target).encode());
+                encoded.extend(Compact(*parts).encode());
+            }
+            encoded.extend(Compact(last.0).encode());
+        }
+    }
+    NposSolution16::decode(&mut &encoded[..]).expect("the solution is encoded as the runtime's; qed")
+}
+
+/// Computes a solution from the snapshot as soon as the signed phase starts and submits it,
+/// then checks the deposit and whether the solution was rewarded, refunded or slashed.
+pub async fn submit_signed_solution(api: &OnlineClient<PolkadotConfig>, acc_seed_accounts : &[sr25519::Pair]) -> Result<(), Box<dyn std::error::Error>> {
+    let signer = &acc_seed_accounts[249];
+    let signer_id: AccountId32 = signer.public().into();
+    // The signed phase only lasts a few blocks, so it is caught on the best chain.
+    let mut sub = api.events().subscribe().await?;
+    let mut start = None;
+    for _ in 0..MAX_ERA_DURATION {
+        let events = match sub.next().await {
+            Some(events) => events?,
+            None => break,
+        };
+        if let Some(started) = events.find_first::<polkadot::election_provider_multi_phase::events::SignedPhaseStarted>()? {
+            start = Some((started.round, events.block_hash()));
+            break;
+        }
+    }
+    let (round, hash) = start.expect("ERROR: No signed phase");
+    let snapshot = api.storage().fetch(&polkadot::storage().election_provider_multi_phase().snapshot(), Some(hash)).await?
+        .expect("ERROR: No election snapshot");
+    let desired_targets = api.storage().fetch(&polkadot::storage().election_provider_multi_phase().desired_targets(), Some(hash)).await?
+        .expect("ERROR: No desired targets");
+    let voters: Vec<(Vec<AccountId32>, u128)> = snapshot.voters.iter()
+        .map(|(_, weight, targets)| (targets.0.clone(), *weight as u128))
+        .collect();
+    let (assignments, score) = phragmen_solution(&voters, &snapshot.targets, desired_targets as usize)
+        .unwrap_or_else(|| panic!("ERROR: The voters do not back {} targets", desired_targets));
+    let raw_solution = RawSolution { solution: encode_solution(&assignments), score, round };
+    let deposit_base = api.constants().at(&polkadot::constants().election_provider_multi_phase().signed_deposit_base())?;
+    let deposit_byte = api.constants().at(&polkadot::constants().election_provider_multi_phase().signed_deposit_byte())?;
+    let deposit_weight = api.constants().at(&polkadot::constants().election_provider_multi_phase().signed_deposit_weight())?;
+    let deposit = deposit_base + deposit_byte * raw_solution.encode().len() as u128;
+    let (minimal_stake, sum_stake) = (raw_solution.score.minimal_stake, raw_solution.score.sum_stake);
+    // The signed and unsigned phases only last a few blocks, so the start of the unsigned phase is
+    // watched for while the solution is submitted.
+    let (result, unsigned_start) = tokio::join!(
+        submit(api, signer, &polkadot::tx().election_provider_multi_phase().submit(raw_solution)),
+        blocks::wait_for_matching_event::<polkadot::election_provider_multi_phase::events::UnsignedPhaseStarted, _>(
+            api,
+            MAX_ERA_DURATION,
+            |e| e.round == round,
+        ),
+    );
+    let events = match result {
+        Err(ref e) if errors::runtime_error_name(api, e).as_deref() == Some("PreDispatchEarlySubmission") => {
+            println!("### The signed phase of round {} ended before the solution was included ###",round);
+            return Ok(());
+        },
+        result => result?,
+    };
+    let stored = events.find_first::<polkadot::election_provider_multi_phase::events::SolutionStored>()?
+        .expect("ERROR: Signed solution not stored");
+    assert!(matches!(stored.election_compute, polkadot::runtime_types::pallet_election_provider_multi_phase::ElectionCompute::Signed));
+    let parent = blocks::parent_hash(api, events.block_hash()).await?;
+    let (_, reserved_before, ..) = balances::account_data(api, &signer_id, Some(parent)).await?;
+    let (_, reserved_after, ..) = balances::account_data(api, &signer_id, Some(events.block_hash())).await?;
+    if deposit_weight == 0 {
+        assert_eq!(reserved_after - reserved_before, deposit);
+    } else {
+        assert!(reserved_after - reserved_before >= deposit);
+    }
+    let deposit = reserved_after - reserved_before;
+    println!("Signed solution submitted for round {}: {} voters, minimal stake {}, total stake {}, deposit {}",round, assignments.len(), minimal_stake, sum_stake, deposit);
+    // The signed phase is settled when the unsigned phase starts.
+    let (_, hash) = unsigned_start?.expect("ERROR: Signed phase not followed by the unsigned phase");
+    let events = api.events().at(Some(hash)).await?;
+    let parent = blocks::parent_hash(api, hash).await?;
+    let (free_before, reserved_before, ..) = balances::account_data(api, &signer_id, Some(parent)).await?;
+    let (free_after, reserved_after, ..) = balances::account_data(api, &signer_id, Some(hash)).await?;
+    assert_eq!(reserved_before - reserved_after, deposit);
+    let mut rewarded = None;
+    for event in events.find::<polkadot::election_provider_multi_phase::events::Rewarded>() {
+        let event = event?;
+        if event.account == signer_id {
+            rewarded = Some(event.value);
+        }
+    }
+    let slashed = events.find::<polkadot::election_provider_multi_phase::events::Slashed>()
+        .any(|e| matches!(e, Ok(e) if e.account == signer_id));
+    assert!(!slashed, "ERROR: Signed solution of round {} slashed", round);
+    match rewarded {
+        // The best solution is queued and rewarded on top of the returned deposit.
+        Some(reward) => {
+            assert_eq!(free_after - free_before, deposit + reward);
+            let queued = api.storage().fetch(&polkadot::storage().election_provider_multi_phase().queued_solution(), Some(hash)).await?
+                .expect("ERROR: Rewarded solution not queued");
+            assert_eq!((queued.score.minimal_stake, queued.score.sum_stake), (minimal_stake, sum_stake));
+            println!("Signed solution of round {} queued, reward {}",round, reward);
+        },
+        // Other solutions are refunded.
+        None => {
+            assert_eq!(free_after - free_before, deposit);
+            println!("Signed solution of round {} refunded, a better one was queued",round);
+        },
+    }
+    Ok(())
+}
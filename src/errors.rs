@@ -0,0 +1,36 @@
+use subxt::{error::DispatchError, OnlineClient, PolkadotConfig};
+
+/// Panics unless `result` is a dispatch failure with the runtime error `name`.
+pub fn assert_module_error<T>(api: &OnlineClient<PolkadotConfig>, result: Result<T, subxt::Error>, name: &str) {
+    let error = match result {
+        Ok(_) => panic!("ERROR: Extrinsic succeeded, expected it to fail with {}", name),
+        Err(e) => runtime_error_name(api, &e)
+            .unwrap_or_else(|| panic!("ERROR: Extrinsic failed with {:?}, expected {}", e, name)),
+    };
+    assert_eq!(error, name, "ERROR: Extrinsic failed with {}, expected {}", error, name);
+}
+
+/// Name of the runtime error an extrinsic failed with, or `None` if it did not fail in the runtime.
+pub fn runtime_error_name(api: &OnlineClient<PolkadotConfig>, error: &subxt::Error) -> Option<String> {
+    match error {
+        subxt::Error::Runtime(DispatchError::Module(e)) => Some(error_name(api, e.error_data.pallet_index, e.error_data.error_index())),
+        subxt::Error::Runtime(DispatchError::Other(encoded)) => Some(dispatch_error_name(api, encoded)),
+        _ => None,
+    }
+}
+
+/// Name of the error with the given index in the pallet with the given index.
+fn error_name(api: &OnlineClient<PolkadotConfig>, pallet: u8, error: u8) -> String {
+    api.metadata().error(pallet, error)
+        .map_or_else(|_| format!("{}/{}", pallet, error), |e| e.error().to_owned())
+}
+
+/// Name of the runtime error in an encoded `DispatchError`, such as the result of a call
+/// dispatched on behalf of another account.
+pub fn dispatch_error_name(api: &OnlineClient<PolkadotConfig>, encoded: &[u8]) -> String {
+    match encoded {
+        [2] => "BadOrigin".to_owned(),
+        [3, pallet, error, ..] => error_name(api, *pallet, *error),
+        _ => format!("{:?}", encoded),
+    }
+}
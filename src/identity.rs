@@ -0,0 +1,235 @@
+use std::collections::HashMap;
+use codec::{Decode, Encode};
+use subxt::{
+    ext::{
+        sp_core::{sr25519, Pair},
+        sp_runtime::{
+            AccountId32,
+            traits::{BlakeTwo256, Hash}
+        },
+    },
+    OnlineClient,
+    PolkadotConfig,
+};
+use crate::consts::*;
+use crate::{balances, errors};
+use crate::tx::{submit, sudo};
+
+#[subxt::subxt(runtime_metadata_path = "./data/metadata.scale")]
+pub mod polkadot {}
+
+type Call = polkadot::runtime_types::edgeware_runtime::Call;
+type IdentityCall = polkadot::runtime_types::pallet_identity::pallet::Call;
+type Data = polkadot::runtime_types::pallet_identity::types::Data;
+type IdentityInfo = polkadot::runtime_types::pallet_identity::types::IdentityInfo;
+type IdentityFields = polkadot::runtime_types::pallet_identity::types::BitFlags<polkadot::runtime_types::pallet_identity::types::IdentityField>;
+type Judgement = polkadot::runtime_types::pallet_identity::types::Judgement<u128>;
+type BoundedVec<T> = polkadot::runtime_types::frame_support::storage::bounded_vec::BoundedVec<T>;
+
+/// Fee charged by the test registrar for its judgements.
+const REGISTRAR_FEE: u128 = EDG;
+/// Bits of the display and email fields in `IdentityFields`.
+const REGISTRAR_FIELDS: u64 = 1 | 16;
+
+/// Raw identity data, at most 32 bytes.
+pub fn raw(bytes: &[u8]) -> Data {
+    assert!(bytes.len() <= 32);
+    let mut encoded = vec![bytes.len() as u8 + 1];
+    encoded.extend(bytes);
+    Data::decode(&mut &encoded[..]).expect("raw data is encoded with its length; qed")
+}
+
+/// Deposit reserved for an identity, which only depends on its number of additional fields.
+pub fn identity_deposit(api: &OnlineClient<PolkadotConfig>, info: &IdentityInfo) -> Result<u128, Box<dyn std::error::Error>> {
+    let basic = api.constants().at(&polkadot::constants().identity().basic_deposit())?;
+    let field = api.constants().at(&polkadot::constants().identity().field_deposit())?;
+    Ok(basic + field * info.additional.0.len() as u128)
+}
+
+/// Identity of a test account using every kind of identity data.
+fn full_identity(i: usize) -> IdentityInfo {
+    let hash = BlakeTwo256::hash(format!("image of test account {}", i).as_bytes());
+    IdentityInfo {
+        additional: BoundedVec(vec![
+            (raw(b"index"), raw(i.to_string().as_bytes())),
+            (raw(b"hashes"), Data::Sha256(hash.0)),
+            (raw(b"empty"), Data::None),
+        ]),
+        display: raw(format!("Test account {}", i).as_bytes()),
+        legal: raw(b"Edgeware test suite"),
+        web: raw(b"https://edgewa.re"),
+        riot: raw(b"@test:matrix.org"),
+        email: raw(format!("test{}@edgewa.re", i).as_bytes()),
+        pgp_fingerprint: Some([i as u8; 20]),
+        image: Data::BlakeTwo256(hash.0),
+        twitter: Data::Keccak256(hash.0),
+    }
+}
+
+/// Sets an identity for `signer`, checking the deposit reserved for it.
+async fn set_identity(api: &OnlineClient<PolkadotConfig>, signer: &sr25519::Pair, info: IdentityInfo) -> Result<(), Box<dyn std::error::Error>> {
+    let acc_id: AccountId32 = signer.public().into();
+    let previous = api.storage().fetch(&polkadot::storage().identity().identity_of(&acc_id), None).await?
+        .map_or(0, |r| r.deposit);
+    let deposit = identity_deposit(api, &info)?;
+    let encoded_info = info.encode();
+    let events = submit(api, signer, &polkadot::tx().identity().set_identity(info)).await?;
+    let set = events.find_first::<polkadot::identity::events::IdentitySet>()?
+        .expect("ERROR: Identity not set");
+    assert_eq!(set.who, acc_id);
+    let (_, reserved) = balances::balance_deltas(api, &acc_id, events.block_hash()).await?;
+    assert_eq!(reserved, deposit as i128 - previous as i128);
+    let registration = api.storage().fetch(&polkadot::storage().identity().identity_of(&acc_id), Some(events.block_hash())).await?
+        .expect("ERROR: Identity not registered");
+    assert_eq!(registration.deposit, deposit);
+    assert_eq!(registration.info.encode(), encoded_info);
+    Ok(())
+}
+
+/// Sets identities with every kind of field and sub-accounts for test accounts, adds a registrar
+/// and has it judge one of the identities, checking the deposits and fees reserved at each step.
+pub async fn register_identities(api: &OnlineClient<PolkadotConfig>, acc_seed_accounts : &[sr25519::Pair], sudo_seed_account: &sr25519::Pair) -> Result<(), Box<dyn std::error::Error>> {
+    let (main, registrar, other) = (&acc_seed_accounts[22], &acc_seed_accounts[23], &acc_seed_accounts[26]);
+    let main_id: AccountId32 = main.public().into();
+    let registrar_id: AccountId32 = registrar.public().into();
+    let other_id: AccountId32 = other.public().into();
+    let (sub_id, quitting_id): (AccountId32, AccountId32) = (acc_seed_accounts[24].public().into(), acc_seed_accounts[25].public().into());
+    let sub_deposit = api.constants().at(&polkadot::constants().identity().sub_account_deposit())?;
+    // Each additional field is charged on top of the basic deposit.
+    set_identity(api, main, full_identity(22)).await?;
+    let mut info = full_identity(22);
+    info.additional.0.truncate(1);
+    set_identity(api, main, info).await?;
+    set_identity(api, other, full_identity(26)).await?;
+    println!("Identities set for test accounts 22 and 26");
+    // Sub-accounts are charged to their main account.
+    let events = submit(api, main, &polkadot::tx().identity().set_subs(vec![(sub_id.clone(), raw(b"sub 24"))])).await?;
+    let (_, reserved) = balances::balance_deltas(api, &main_id, events.block_hash()).await?;
+    assert_eq!(reserved, sub_deposit as i128);
+    let events = submit(api, main, &polkadot::tx().identity().add_sub(quitting_id.clone().into(), raw(b"sub 25"))).await?;
+    let added = events.find_first::<polkadot::identity::events::SubIdentityAdded>()?
+        .expect("ERROR: Sub-account not added");
+    assert_eq!((added.sub, added.main.clone(), added.deposit), (quitting_id.clone(), main_id.clone(), sub_deposit));
+    submit(api, main, &polkadot::tx().identity().rename_sub(quitting_id.clone().into(), raw(b"renamed sub 25"))).await?;
+    let (_, name) = api.storage().fetch(&polkadot::storage().identity().super_of(&quitting_id), None).await?
+        .expect("ERROR: Sub-account not registered");
+    assert_eq!(name.encode(), raw(b"renamed sub 25").encode());
+    // A sub-account quitting receives the deposit of its main account.
+    let events = submit(api, &acc_seed_accounts[25], &polkadot::tx().identity().quit_sub()).await?;
+    let revoked = events.find_first::<polkadot::identity::events::SubIdentityRevoked>()?
+        .expect("ERROR: Sub-account not revoked");
+    assert_eq!((revoked.sub, revoked.main, revoked.deposit), (quitting_id.clone(), main_id.clone(), sub_deposit));
+    let (_, reserved) = balances::balance_deltas(api, &main_id, events.block_hash()).await?;
+    assert_eq!(reserved, -(sub_deposit as i128));
+    let (deposit, subs) = api.storage().fetch_or_default(&polkadot::storage().identity().subs_of(&main_id), Some(events.block_hash())).await?;
+    assert_eq!((deposit, subs.0), (sub_deposit, vec![sub_id.clone()]));
+    let (super_id, _) = api.storage().fetch(&polkadot::storage().identity().super_of(&sub_id), Some(events.block_hash())).await?
+        .expect("ERROR: Sub-account not registered");
+    assert_eq!(super_id, main_id);
+    assert!(api.storage().fetch(&polkadot::storage().identity().super_of(&quitting_id), Some(events.block_hash())).await?.is_none());
+    println!("Sub-account of test account 22 set");
+    // Registrars are added by root and set their own fee and fields.
+    let events = sudo(api, sudo_seed_account, Call::Identity(IdentityCall::add_registrar { account: registrar_id.clone() })).await?;
+    let index = events.find_first::<polkadot::identity::events::RegistrarAdded>()?
+        .expect("ERROR: Registrar not added").registrar_index;
+    submit(api, registrar, &polkadot::tx().identity().set_fee(index, REGISTRAR_FEE)).await?;
+    submit(api, registrar, &polkadot::tx().identity().set_fields(index, IdentityFields::decode(&mut &REGISTRAR_FIELDS.encode()[..]).expect("fields are encoded as their bits; qed"))).await?;
+    let registrars = api.storage().fetch_or_default(&polkadot::storage().identity().registrars(), None).await?;
+    let info = registrars.0[index as usize].as_ref().expect("ERROR: Registrar not registered");
+    assert_eq!((info.account.clone(), info.fee, info.fields.0), (registrar_id.clone(), REGISTRAR_FEE, REGISTRAR_FIELDS));
+    println!("Registrar {} added",index);
+    // Judgements are only requested up to a maximal fee, which is reserved until the judgement.
+    errors::assert_module_error(api, submit(api, main, &polkadot::tx().identity().request_judgement(index, REGISTRAR_FEE - 1)).await, "FeeChanged");
+    let events = submit(api, other, &polkadot::tx().identity().request_judgement(index, REGISTRAR_FEE)).await?;
+    let (_, reserved) = balances::balance_deltas(api, &other_id, events.block_hash()).await?;
+    assert_eq!(reserved, REGISTRAR_FEE as i128);
+    let events = submit(api, other, &polkadot::tx().identity().cancel_request(index)).await?;
+    let unrequested = events.find_first::<polkadot::identity::events::JudgementUnrequested>()?
+        .expect("ERROR: Judgement request not canceled");
+    assert_eq!((unrequested.who, unrequested.registrar_index), (other_id.clone(), index));
+    let (_, reserved) = balances::balance_deltas(api, &other_id, events.block_hash()).await?;
+    assert_eq!(reserved, -(REGISTRAR_FEE as i128));
+    let events = submit(api, main, &polkadot::tx().identity().request_judgement(index, REGISTRAR_FEE)).await?;
+    let requested = events.find_first::<polkadot::identity::events::JudgementRequested>()?
+        .expect("ERROR: Judgement not requested");
+    assert_eq!((requested.who, requested.registrar_index), (main_id.clone(), index));
+    let registration = api.storage().fetch(&polkadot::storage().identity().identity_of(&main_id), Some(events.block_hash())).await?
+        .expect("ERROR: Identity removed");
+    assert!(registration.judgements.0.iter().any(|(i, j)| *i == index && matches!(j, Judgement::FeePaid(fee) if *fee == REGISTRAR_FEE)));
+    // The registrar is paid the fee with its judgement.
+    errors::assert_module_error(api, submit(api, registrar, &polkadot::tx().identity().provide_judgement(index, main_id.clone().into(), Judgement::FeePaid(0))).await, "InvalidJudgement");
+    let events = submit(api, registrar, &polkadot::tx().identity().provide_judgement(index, main_id.clone().into(), Judgement::Reasonable)).await?;
+    let given = events.find_first::<polkadot::identity::events::JudgementGiven>()?
+        .expect("ERROR: Judgement not given");
+    assert_eq!((given.target, given.registrar_index), (main_id.clone(), index));
+    let (_, reserved) = balances::balance_deltas(api, &main_id, events.block_hash()).await?;
+    assert_eq!(reserved, -(REGISTRAR_FEE as i128));
+    let registration = api.storage().fetch(&polkadot::storage().identity().identity_of(&main_id), Some(events.block_hash())).await?
+        .expect("ERROR: Identity removed");
+    assert!(registration.judgements.0.iter().any(|(i, j)| *i == index && matches!(j, Judgement::Reasonable)));
+    errors::assert_module_error(api, submit(api, main, &polkadot::tx().identity().cancel_request(index)).await, "JudgementGiven");
+    println!("Identity of test account 22 judged reasonable by registrar {}",index);
+    Ok(())
+}
+
+/// Encoded identities, super accounts and sub-accounts of the test accounts.
+pub async fn dump_identities(api: &OnlineClient<PolkadotConfig>, acc_seed_accounts : &[sr25519::Pair]) -> Result<HashMap<AccountId32, Vec<u8>>, Box<dyn std::error::Error>> {
+    let mut identities = HashMap::new();
+    for acc in acc_seed_accounts.iter() {
+        let acc_id: AccountId32 = acc.public().into();
+        let identity = api.storage().fetch(&polkadot::storage().identity().identity_of(&acc_id), None).await?;
+        let super_of = api.storage().fetch(&polkadot::storage().identity().super_of(&acc_id), None).await?;
+        let subs = api.storage().fetch(&polkadot::storage().identity().subs_of(&acc_id), None).await?;
+        if identity.is_some() || super_of.is_some() || subs.is_some() {
+            identities.insert(acc_id, (identity, super_of, subs).encode());
+        }
+    }
+    Ok(identities)
+}
+
+/// Checks the identities of the test accounts are the ones recorded before the upgrade.
+pub async fn verify_identities(api: &OnlineClient<PolkadotConfig>, acc_seed_accounts : &[sr25519::Pair], before: &HashMap<AccountId32, Vec<u8>>) -> Result<(), Box<dyn std::error::Error>> {
+    let after = dump_identities(api, acc_seed_accounts).await?;
+    for (acc_id, identity) in before.iter() {
+        match after.get(acc_id) {
+            Some(i) if i == identity => {},
+            Some(_) => panic!("ERROR: Identity of {} changed by the upgrade", acc_id),
+            None => panic!("ERROR: Identity of {} removed by the upgrade", acc_id),
+        }
+    }
+    assert_eq!(after.len(), before.len(), "ERROR: Identities created by the upgrade");
+    println!("{} identities preserved by the upgrade",before.len());
+    Ok(())
+}
+
+/// Clears the identity of a test account, returning its deposits, and has root kill another one,
+/// slashing its deposit.
+pub async fn clear_identities(api: &OnlineClient<PolkadotConfig>, acc_seed_accounts : &[sr25519::Pair], sudo_seed_account: &sr25519::Pair) -> Result<(), Box<dyn std::error::Error>> {
+    let (main, other) = (&acc_seed_accounts[22], &acc_seed_accounts[26]);
+    let main_id: AccountId32 = main.public().into();
+    let other_id: AccountId32 = other.public().into();
+    let sub_id: AccountId32 = acc_seed_accounts[24].public().into();
+    let registration = api.storage().fetch(&polkadot::storage().identity().identity_of(&main_id), None).await?
+        .expect("ERROR: No identity to clear");
+    let (sub_deposit, _) = api.storage().fetch_or_default(&polkadot::storage().identity().subs_of(&main_id), None).await?;
+    let events = submit(api, main, &polkadot::tx().identity().clear_identity()).await?;
+    let cleared = events.find_first::<polkadot::identity::events::IdentityCleared>()?
+        .expect("ERROR: Identity not cleared");
+    assert_eq!((cleared.who, cleared.deposit), (main_id.clone(), registration.deposit + sub_deposit));
+    let (_, reserved) = balances::balance_deltas(api, &main_id, events.block_hash()).await?;
+    assert_eq!(reserved, -((registration.deposit + sub_deposit) as i128));
+    assert!(api.storage().fetch(&polkadot::storage().identity().identity_of(&main_id), Some(events.block_hash())).await?.is_none());
+    assert!(api.storage().fetch(&polkadot::storage().identity().super_of(&sub_id), Some(events.block_hash())).await?.is_none());
+    errors::assert_module_error(api, submit(api, main, &polkadot::tx().identity().clear_identity()).await, "NotNamed");
+    println!("Identity of test account 22 cleared");
+    let registration = api.storage().fetch(&polkadot::storage().identity().identity_of(&other_id), None).await?
+        .expect("ERROR: No identity to kill");
+    let events = sudo(api, sudo_seed_account, Call::Identity(IdentityCall::kill_identity { target: other_id.clone().into() })).await?;
+    let killed = events.find_first::<polkadot::identity::events::IdentityKilled>()?
+        .expect("ERROR: Identity not killed");
+    assert_eq!((killed.who, killed.deposit), (other_id.clone(), registration.deposit));
+    let (free, reserved) = balances::balance_deltas(api, &other_id, events.block_hash()).await?;
+    assert_eq!((free, reserved), (0, -(registration.deposit as i128)));
+    println!("Identity of test account 26 killed");
+    Ok(())
+}
@@ -0,0 +1,108 @@
+use rand::Rng;
+use subxt::{
+    ext::{
+        sp_core::{sr25519, Pair, H256},
+        sp_runtime::{AccountId32, MultiAddress},
+    },
+    OnlineClient,
+    PolkadotConfig,
+};
+use crate::consts::*;
+use crate::{balances, errors};
+use crate::tx::{submit, sudo};
+
+#[subxt::subxt(runtime_metadata_path = "./data/metadata.scale")]
+pub mod polkadot {}
+
+type Call = polkadot::runtime_types::edgeware_runtime::Call;
+type IndicesCall = polkadot::runtime_types::pallet_indices::pallet::Call;
+
+/// Checks the owner, deposit and frozen flag of an index in the block with the given hash.
+async fn check_index(api: &OnlineClient<PolkadotConfig>, index: u32, owner: &AccountId32, deposit: u128, frozen: bool, hash: H256) -> Result<(), Box<dyn std::error::Error>> {
+    let (who, d, f) = api.storage().fetch(&polkadot::storage().indices().accounts(&index), Some(hash)).await?
+        .expect("ERROR: Index not assigned");
+    assert_eq!((&who, d, f), (owner, deposit, frozen));
+    Ok(())
+}
+
+/// Random account index which is not assigned yet.
+async fn unassigned_index(api: &OnlineClient<PolkadotConfig>) -> Result<u32, Box<dyn std::error::Error>> {
+    loop {
+        let index = rand::thread_rng().gen_range(1_000_000..u32::MAX);
+        if api.storage().fetch(&polkadot::storage().indices().accounts(&index), None).await?.is_none() {
+            return Ok(index);
+        }
+    }
+}
+
+/// Has test accounts claim, use, transfer and free an account index, then has root force its
+/// transfer and its owner freeze it, checking the deposit at each step.
+pub async fn index_lifecycle(api: &OnlineClient<PolkadotConfig>, acc_seed_accounts : &[sr25519::Pair], sudo_seed_account: &sr25519::Pair) -> Result<(), Box<dyn std::error::Error>> {
+    let (owner, other, frozen_owner) = (&acc_seed_accounts[42], &acc_seed_accounts[43], &acc_seed_accounts[44]);
+    let owner_id: AccountId32 = owner.public().into();
+    let other_id: AccountId32 = other.public().into();
+    let frozen_owner_id: AccountId32 = frozen_owner.public().into();
+    let deposit = api.constants().at(&polkadot::constants().indices().deposit())?;
+    let index = unassigned_index(api).await?;
+    let unassigned = unassigned_index(api).await?;
+    // Claiming an index reserves the deposit.
+    let events = submit(api, owner, &polkadot::tx().indices().claim(index)).await?;
+    let assigned = events.find_first::<polkadot::indices::events::IndexAssigned>()?
+        .expect("ERROR: Index not assigned");
+    assert_eq!((assigned.who, assigned.index), (owner_id.clone(), index));
+    let (_, reserved) = balances::balance_deltas(api, &owner_id, events.block_hash()).await?;
+    assert_eq!(reserved, deposit as i128);
+    check_index(api, index, &owner_id, deposit, false, events.block_hash()).await?;
+    errors::assert_module_error(api, submit(api, other, &polkadot::tx().indices().claim(index)).await, "InUse");
+    println!("Index {} claimed by test account 42",index);
+    // Extrinsics addressed to the index reach its owner.
+    let events = submit(api, other, &polkadot::tx().balances().transfer(MultiAddress::Index(index), 5 * EDG)).await?;
+    let transfer = events.find_first::<polkadot::balances::events::Transfer>()?
+        .expect("ERROR: Transfer to an index failed");
+    assert_eq!((transfer.from, transfer.to, transfer.amount), (other_id.clone(), owner_id.clone(), 5 * EDG));
+    let (free, _) = balances::balance_deltas(api, &owner_id, events.block_hash()).await?;
+    assert_eq!(free, 5 * EDG as i128);
+    let result = submit(api, other, &polkadot::tx().balances().transfer(MultiAddress::Index(unassigned), 5 * EDG)).await;
+    assert!(result.is_err(), "ERROR: Transfer to an unassigned index accepted");
+    // Transfers move the deposit to the new owner, which can free the index.
+    let events = submit(api, owner, &polkadot::tx().indices().transfer(other_id.clone(), index)).await?;
+    let (_, reserved) = balances::balance_deltas(api, &owner_id, events.block_hash()).await?;
+    assert_eq!(reserved, -(deposit as i128));
+    let (_, reserved) = balances::balance_deltas(api, &other_id, events.block_hash()).await?;
+    assert_eq!(reserved, deposit as i128);
+    check_index(api, index, &other_id, deposit, false, events.block_hash()).await?;
+    errors::assert_module_error(api, submit(api, owner, &polkadot::tx().indices().transfer(frozen_owner_id.clone(), index)).await, "NotOwner");
+    errors::assert_module_error(api, submit(api, other, &polkadot::tx().indices().transfer(other_id.clone(), index)).await, "NotTransfer");
+    let events = submit(api, other, &polkadot::tx().indices().free(index)).await?;
+    let freed = events.find_first::<polkadot::indices::events::IndexFreed>()?
+        .expect("ERROR: Index not freed");
+    assert_eq!(freed.index, index);
+    let (_, reserved) = balances::balance_deltas(api, &other_id, events.block_hash()).await?;
+    assert_eq!(reserved, -(deposit as i128));
+    assert!(api.storage().fetch(&polkadot::storage().indices().accounts(&index), Some(events.block_hash())).await?.is_none());
+    errors::assert_module_error(api, submit(api, other, &polkadot::tx().indices().free(index)).await, "NotAssigned");
+    println!("Index {} transferred to test account 43 and freed",index);
+    // A forced transfer returns the deposit of the previous owner and charges none to the new one.
+    submit(api, owner, &polkadot::tx().indices().claim(index)).await?;
+    let events = sudo(api, sudo_seed_account, Call::Indices(IndicesCall::force_transfer { new: frozen_owner_id.clone(), index, freeze: false })).await?;
+    let assigned = events.find_first::<polkadot::indices::events::IndexAssigned>()?
+        .expect("ERROR: Index not assigned");
+    assert_eq!((assigned.who, assigned.index), (frozen_owner_id.clone(), index));
+    let (_, reserved) = balances::balance_deltas(api, &owner_id, events.block_hash()).await?;
+    assert_eq!(reserved, -(deposit as i128));
+    check_index(api, index, &frozen_owner_id, 0, false, events.block_hash()).await?;
+    // A frozen index is permanently assigned.
+    let events = submit(api, frozen_owner, &polkadot::tx().indices().freeze(index)).await?;
+    let frozen = events.find_first::<polkadot::indices::events::IndexFrozen>()?
+        .expect("ERROR: Index not frozen");
+    assert_eq!((frozen.index, frozen.who), (index, frozen_owner_id.clone()));
+    check_index(api, index, &frozen_owner_id, 0, true, events.block_hash()).await?;
+    errors::assert_module_error(api, submit(api, frozen_owner, &polkadot::tx().indices().free(index)).await, "Permanent");
+    errors::assert_module_error(api, submit(api, frozen_owner, &polkadot::tx().indices().transfer(owner_id.clone(), index)).await, "Permanent");
+    let events = submit(api, other, &polkadot::tx().balances().transfer(MultiAddress::Index(index), EDG)).await?;
+    let transfer = events.find_first::<polkadot::balances::events::Transfer>()?
+        .expect("ERROR: Transfer to a frozen index failed");
+    assert_eq!(transfer.to, frozen_owner_id);
+    println!("Index {} force transferred to test account 44 and frozen",index);
+    Ok(())
+}
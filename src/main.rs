@@ -22,10 +22,25 @@ use serde::Deserialize;
 pub mod polkadot {}
 
 pub mod balances;
+pub mod blocks;
 pub mod council;
 pub mod staking;
+pub mod election;
+pub mod treasury;
+pub mod bounties;
+pub mod tips;
 pub mod democracy;
+pub mod identity;
+pub mod proxy;
+pub mod multisig;
+pub mod vesting;
+pub mod indices;
+pub mod scheduler;
+pub mod utility;
+pub mod recovery;
 pub mod consts;
+pub mod errors;
+pub mod tx;
 use consts::*;
 
 type Call = polkadot::runtime_types::edgeware_runtime::Call;
@@ -36,6 +51,7 @@ type BalancesCall = polkadot::runtime_types::pallet_balances::pallet::Call;
 struct Config {
     sudo_seed: String,
     host: String,
+    nomination_strategy: Option<staking::NominationStrategy>,
 
 }
 
@@ -86,8 +102,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         println!("Balance transfer extrinsic submitted for test account {}: {}",i, hash);
     }
     // Nominate validators and set up a council
-    staking::nominate_all(&api, &acc_seed_accounts[..]).await?;
+    let nomination_strategy = config.nomination_strategy.unwrap_or(staking::NominationStrategy::First);
+    let nomination_targets = staking::nominate_all(&api, &acc_seed_accounts[..], nomination_strategy).await?;
     council::populate_council(&api, &acc_seed_accounts[..]).await?;
+    staking::verify_nominations(&api, &acc_seed_accounts[..], &nomination_targets).await?;
+    identity::register_identities(&api, &acc_seed_accounts[..], &sudo_seed_account).await?;
+    vesting::create_schedules(&api, &acc_seed_accounts[..]).await?;
     let referendum_storage_index = polkadot::storage().democracy().referendum_count();
     // Propose the upgrade through democracy
     democracy::propose_upgrade(&api, &acc_seed_accounts[..]).await?;
@@ -103,6 +123,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     };
     // Record all the balances data
     let account_data_before = balances::dump_balances(&api).await?;
+    let identities_before = identity::dump_identities(&api, &acc_seed_accounts[..]).await?;
+    let schedules_before = vesting::dump_schedules(&api, &acc_seed_accounts[..]).await?;
     // Approve the upgrade
     democracy::vote(&api, &acc_seed_accounts[..], referendum_index, true).await?;
     tokio::time::sleep(Duration::from_secs(60*3)).await;
@@ -126,7 +148,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             println!("Balances of account {} have been created: {} {} {} {}.",a, b0,b1,b2,b3);
         }
     }
-    council::external_majority_workflow(&api, &acc_seed_accounts[..]).await?;
+    identity::verify_identities(&api, &acc_seed_accounts[..], &identities_before).await?;
+    identity::clear_identities(&api, &acc_seed_accounts[..], &sudo_seed_account).await?;
+    vesting::verify_schedules(&api, &acc_seed_accounts[..], &schedules_before).await?;
+    vesting::vest_and_merge(&api, &acc_seed_accounts[..]).await?;
+    council::motion_lifecycle(&api, &acc_seed_accounts[..], &sudo_seed_account).await?;
+    let treasury_proposal_index = council::external_majority_workflow(&api, &acc_seed_accounts[..]).await?;
     tokio::time::sleep(Duration::from_secs(60+BLOCK_INCLUSION_LAG)).await;
     let referendum_index = api.storage().fetch(&referendum_storage_index, None).await?;
     let referendum_index = if let Some(t) = referendum_index {
@@ -137,6 +164,34 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     } else {
         panic!("ERROR: Democracy proposal incorrectly registered");
     };
-    democracy::vote(&api, &acc_seed_accounts[..], referendum_index, false).await?;
+    // Approve the treasury proposal and follow it to the payout.
+    treasury::fund(&api, &sudo_seed_account, TEST_ACCOUNT_FUNDING).await?;
+    democracy::vote(&api, &acc_seed_accounts[..], referendum_index, true).await?;
+    treasury::verify_payout(&api, treasury_proposal_index, referendum_index).await?;
+    treasury::rejection_and_spend_period(&api, &acc_seed_accounts[..], &sudo_seed_account).await?;
+    bounties::bounty_lifecycle(&api, &acc_seed_accounts[..], &sudo_seed_account).await?;
+    tips::tip_lifecycle(&api, &acc_seed_accounts[..], &sudo_seed_account).await?;
+    proxy::proxy_types(&api, &acc_seed_accounts[..]).await?;
+    proxy::announced_proxy(&api, &acc_seed_accounts[..]).await?;
+    proxy::anonymous_proxy(&api, &acc_seed_accounts[..]).await?;
+    multisig::threshold_approvals(&api, &acc_seed_accounts[..]).await?;
+    multisig::cancellation(&api, &acc_seed_accounts[..]).await?;
+    indices::index_lifecycle(&api, &acc_seed_accounts[..], &sudo_seed_account).await?;
+    scheduler::scheduler_lifecycle(&api, &acc_seed_accounts[..], &sudo_seed_account).await?;
+    utility::batch_semantics(&api, &acc_seed_accounts[..]).await?;
+    recovery::social_recovery(&api, &acc_seed_accounts[..]).await?;
+    // Verify the ordering of the public proposals queue.
+    democracy::public_proposal_queue(&api, &acc_seed_accounts[..]).await?;
+    // Verify the exposures resulting from the nominations.
+    staking::verify_exposures(&api, &acc_seed_accounts[..]).await?;
+    staking::unbonding_lifecycle(&api, &acc_seed_accounts[..], &sudo_seed_account).await?;
+    staking::payout_rewards(&api, &acc_seed_accounts[..], &sudo_seed_account).await?;
+    staking::nomination_rejections(&api, &acc_seed_accounts[..]).await?;
+    staking::validator_lifecycle(&api, &acc_seed_accounts[..], &sudo_seed_account).await?;
+    staking::slashing_scenario(&api, &acc_seed_accounts[..], &sudo_seed_account).await?;
+    // Verify the voter list and the next election.
+    election::verify_voter_bags(&api, &acc_seed_accounts[..]).await?;
+    election::verify_election_phases(&api, &acc_seed_accounts[..]).await?;
+    election::submit_signed_solution(&api, &acc_seed_accounts[..]).await?;
     Ok(())
 }
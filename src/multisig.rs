@@ -0,0 +1,157 @@
+use codec::{Decode, Encode};
+use subxt::{
+    ext::{
+        sp_core::{blake2_256, sr25519, Pair},
+        sp_runtime::AccountId32,
+    },
+    OnlineClient,
+    PolkadotConfig,
+};
+use crate::consts::*;
+use crate::{balances, errors};
+use crate::tx::submit;
+
+#[subxt::subxt(runtime_metadata_path = "./data/metadata.scale")]
+pub mod polkadot {}
+
+type Call = polkadot::runtime_types::edgeware_runtime::Call;
+type BalancesCall = polkadot::runtime_types::pallet_balances::pallet::Call;
+type OpaqueCall = polkadot::runtime_types::frame_support::traits::misc::WrapperKeepOpaque<Call>;
+type Timepoint = polkadot::runtime_types::pallet_multisig::Timepoint<u32>;
+
+/// Maximal weight of the calls dispatched by the test multisig accounts.
+const MAX_CALL_WEIGHT: u64 = 10_000_000_000;
+
+/// Account of the multisig of `signatories` with the given threshold.
+pub fn multisig_account(signatories: &[AccountId32], threshold: u16) -> AccountId32 {
+    let mut signatories = signatories.to_vec();
+    signatories.sort();
+    let entropy = blake2_256(&(b"modlpy/utilisuba", signatories, threshold).encode());
+    AccountId32::decode(&mut &entropy[..]).expect("an account id is 32 bytes; qed")
+}
+
+/// Deposit reserved from the first approval of a multisig operation.
+pub fn multisig_deposit(api: &OnlineClient<PolkadotConfig>, threshold: u16) -> Result<u128, Box<dyn std::error::Error>> {
+    let base = api.constants().at(&polkadot::constants().multisig().deposit_base())?;
+    let factor = api.constants().at(&polkadot::constants().multisig().deposit_factor())?;
+    Ok(base + factor * threshold as u128)
+}
+
+/// Deposit reserved for storing a call of `len` bytes with its operation.
+pub fn call_deposit(api: &OnlineClient<PolkadotConfig>, len: usize) -> Result<u128, Box<dyn std::error::Error>> {
+    let base = api.constants().at(&polkadot::constants().multisig().deposit_base())?;
+    let factor = api.constants().at(&polkadot::constants().multisig().deposit_factor())?;
+    Ok(base + factor * ((len + 31) / 32) as u128)
+}
+
+/// Call kept encoded, as multisig operations store them.
+fn opaque(call: &Call) -> OpaqueCall {
+    OpaqueCall::decode(&mut &call.encode().encode()[..]).expect("opaque calls are encoded as bytes; qed")
+}
+
+/// Signatories of a multisig other than `signer`, sorted as the runtime requires.
+fn others(signatories: &[AccountId32], signer: &sr25519::Pair) -> Vec<AccountId32> {
+    let signer_id: AccountId32 = signer.public().into();
+    let mut others: Vec<AccountId32> = signatories.iter().filter(|s| **s != signer_id).cloned().collect();
+    others.sort();
+    others
+}
+
+/// Funds a 2 of 3 multisig of test accounts and has it transfer funds once enough signatories
+/// approved, checking the deposits and timepoint errors on the way.
+pub async fn threshold_approvals(api: &OnlineClient<PolkadotConfig>, acc_seed_accounts : &[sr25519::Pair]) -> Result<(), Box<dyn std::error::Error>> {
+    let signers = &acc_seed_accounts[34..37];
+    let signatories: Vec<AccountId32> = signers.iter().map(|s| s.public().into()).collect();
+    let beneficiary: AccountId32 = acc_seed_accounts[37].public().into();
+    let threshold = 2;
+    let multisig = multisig_account(&signatories, threshold);
+    submit(api, &signers[0], &polkadot::tx().balances().transfer(multisig.clone().into(), 100 * EDG)).await?;
+    let call = Call::Balances(BalancesCall::transfer { dest: beneficiary.clone().into(), value: 10 * EDG });
+    let call_hash = blake2_256(&call.encode());
+    // The first approval reserves the deposit and sets the timepoint of the operation.
+    let tx = polkadot::tx().multisig().approve_as_multi(threshold, others(&signatories, &signers[0]), Some(Timepoint { height: 1, index: 0 }), call_hash, 0);
+    errors::assert_module_error(api, submit(api, &signers[0], &tx).await, "UnexpectedTimepoint");
+    let tx = polkadot::tx().multisig().approve_as_multi(threshold, others(&signatories, &signers[0]), None, call_hash, 0);
+    let events = submit(api, &signers[0], &tx).await?;
+    let new = events.find_first::<polkadot::multisig::events::NewMultisig>()?
+        .expect("ERROR: Multisig operation not created");
+    assert_eq!((new.approving, new.multisig.clone(), new.call_hash), (signatories[0].clone(), multisig.clone(), call_hash));
+    let (_, reserved) = balances::balance_deltas(api, &signatories[0], events.block_hash()).await?;
+    assert_eq!(reserved, multisig_deposit(api, threshold)? as i128);
+    let operation = api.storage().fetch(&polkadot::storage().multisig().multisigs(&multisig, &call_hash), Some(events.block_hash())).await?
+        .expect("ERROR: Multisig operation not registered");
+    assert_eq!((operation.deposit, operation.depositor, operation.approvals), (multisig_deposit(api, threshold)?, signatories[0].clone(), vec![signatories[0].clone()]));
+    let timepoint = || Timepoint { height: operation.when.height, index: operation.when.index };
+    let tx = polkadot::tx().multisig().approve_as_multi(threshold, others(&signatories, &signers[0]), Some(timepoint()), call_hash, 0);
+    errors::assert_module_error(api, submit(api, &signers[0], &tx).await, "AlreadyApproved");
+    // Later approvals need the timepoint of the operation.
+    let tx = polkadot::tx().multisig().as_multi(threshold, others(&signatories, &signers[1]), None, opaque(&call), false, MAX_CALL_WEIGHT);
+    errors::assert_module_error(api, submit(api, &signers[1], &tx).await, "NoTimepoint");
+    let wrong = Timepoint { height: operation.when.height + 1, index: operation.when.index };
+    let tx = polkadot::tx().multisig().as_multi(threshold, others(&signatories, &signers[1]), Some(wrong), opaque(&call), false, MAX_CALL_WEIGHT);
+    errors::assert_module_error(api, submit(api, &signers[1], &tx).await, "WrongTimepoint");
+    let tx = polkadot::tx().multisig().as_multi(threshold, others(&signatories, &signers[1]), Some(timepoint()), opaque(&call), false, 0);
+    errors::assert_module_error(api, submit(api, &signers[1], &tx).await, "MaxWeightTooLow");
+    // The call is dispatched with the approval reaching the threshold.
+    let (free, ..) = balances::account_data(api, &beneficiary, None).await?;
+    assert!(api.storage().fetch(&polkadot::storage().multisig().multisigs(&multisig, &call_hash), None).await?.is_some());
+    let tx = polkadot::tx().multisig().as_multi(threshold, others(&signatories, &signers[1]), Some(timepoint()), opaque(&call), false, MAX_CALL_WEIGHT);
+    let events = submit(api, &signers[1], &tx).await?;
+    let executed = events.find_first::<polkadot::multisig::events::MultisigExecuted>()?
+        .expect("ERROR: Multisig call not executed");
+    assert_eq!((executed.approving, executed.multisig, executed.call_hash), (signatories[1].clone(), multisig.clone(), call_hash));
+    assert!(executed.result.is_ok(), "ERROR: Multisig call failed: {:?}", executed.result);
+    let (free_after, ..) = balances::account_data(api, &beneficiary, Some(events.block_hash())).await?;
+    assert_eq!(free_after - free, 10 * EDG);
+    let (multisig_free, _) = balances::balance_deltas(api, &multisig, events.block_hash()).await?;
+    assert_eq!(multisig_free, -10 * EDG as i128);
+    let (_, reserved) = balances::balance_deltas(api, &signatories[0], events.block_hash()).await?;
+    assert_eq!(reserved, -(multisig_deposit(api, threshold)? as i128));
+    assert!(api.storage().fetch(&polkadot::storage().multisig().multisigs(&multisig, &call_hash), Some(events.block_hash())).await?.is_none());
+    println!("Multisig {} transferred {} once {} of {} signatories approved",multisig, 10 * EDG, threshold, signatories.len());
+    Ok(())
+}
+
+/// Opens a multisig operation storing its call, which only its depositor may cancel, releasing
+/// both deposits.
+pub async fn cancellation(api: &OnlineClient<PolkadotConfig>, acc_seed_accounts : &[sr25519::Pair]) -> Result<(), Box<dyn std::error::Error>> {
+    let signers = &acc_seed_accounts[34..37];
+    let signatories: Vec<AccountId32> = signers.iter().map(|s| s.public().into()).collect();
+    let beneficiary: AccountId32 = acc_seed_accounts[37].public().into();
+    let threshold = 3;
+    let multisig = multisig_account(&signatories, threshold);
+    let call = Call::Balances(BalancesCall::transfer { dest: beneficiary.into(), value: EDG });
+    let call_hash = blake2_256(&call.encode());
+    let len = call.encode().len();
+    let tx = polkadot::tx().multisig().as_multi(threshold, others(&signatories, &signers[0]), None, opaque(&call), true, 0);
+    let events = submit(api, &signers[0], &tx).await?;
+    assert!(events.has::<polkadot::multisig::events::NewMultisig>()?);
+    let (_, reserved) = balances::balance_deltas(api, &signatories[0], events.block_hash()).await?;
+    assert_eq!(reserved, (multisig_deposit(api, threshold)? + call_deposit(api, len)?) as i128);
+    let (_, depositor, deposit) = api.storage().fetch(&polkadot::storage().multisig().calls(&call_hash), Some(events.block_hash())).await?
+        .expect("ERROR: Multisig call not stored");
+    assert_eq!((depositor, deposit), (signatories[0].clone(), call_deposit(api, len)?));
+    let operation = api.storage().fetch(&polkadot::storage().multisig().multisigs(&multisig, &call_hash), Some(events.block_hash())).await?
+        .expect("ERROR: Multisig operation not registered");
+    let timepoint = || Timepoint { height: operation.when.height, index: operation.when.index };
+    // A stored call is not stored again when the operation is approved.
+    let tx = polkadot::tx().multisig().as_multi(threshold, others(&signatories, &signers[0]), Some(timepoint()), opaque(&call), true, 0);
+    errors::assert_module_error(api, submit(api, &signers[0], &tx).await, "AlreadyStored");
+    // Only the depositor cancels an operation.
+    let tx = polkadot::tx().multisig().cancel_as_multi(threshold, others(&signatories, &signers[1]), timepoint(), call_hash);
+    errors::assert_module_error(api, submit(api, &signers[1], &tx).await, "NotOwner");
+    let wrong = Timepoint { height: operation.when.height, index: operation.when.index + 1 };
+    let tx = polkadot::tx().multisig().cancel_as_multi(threshold, others(&signatories, &signers[0]), wrong, call_hash);
+    errors::assert_module_error(api, submit(api, &signers[0], &tx).await, "WrongTimepoint");
+    let tx = polkadot::tx().multisig().cancel_as_multi(threshold, others(&signatories, &signers[0]), timepoint(), call_hash);
+    let events = submit(api, &signers[0], &tx).await?;
+    let cancelled = events.find_first::<polkadot::multisig::events::MultisigCancelled>()?
+        .expect("ERROR: Multisig operation not cancelled");
+    assert_eq!((cancelled.cancelling, cancelled.multisig, cancelled.call_hash), (signatories[0].clone(), multisig.clone(), call_hash));
+    let (_, reserved) = balances::balance_deltas(api, &signatories[0], events.block_hash()).await?;
+    assert_eq!(reserved, -((multisig_deposit(api, threshold)? + call_deposit(api, len)?) as i128));
+    assert!(api.storage().fetch(&polkadot::storage().multisig().multisigs(&multisig, &call_hash), Some(events.block_hash())).await?.is_none());
+    assert!(api.storage().fetch(&polkadot::storage().multisig().calls(&call_hash), Some(events.block_hash())).await?.is_none());
+    println!("Multisig operation of {} cancelled by its depositor",multisig);
+    Ok(())
+}
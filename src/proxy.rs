@@ -0,0 +1,214 @@
+use codec::Encode;
+use subxt::{
+    tx::TxEvents,
+    ext::{
+        sp_core::{sr25519, Pair},
+        sp_runtime::{
+            AccountId32,
+            traits::{BlakeTwo256, Hash}
+        },
+    },
+    OnlineClient,
+    PolkadotConfig,
+};
+use crate::consts::*;
+use crate::{balances, blocks, errors};
+use crate::tx::submit;
+
+#[subxt::subxt(runtime_metadata_path = "./data/metadata.scale")]
+pub mod polkadot {}
+
+type Call = polkadot::runtime_types::edgeware_runtime::Call;
+type ProxyType = polkadot::runtime_types::edgeware_runtime::ProxyType;
+type SystemCall = polkadot::runtime_types::frame_system::pallet::Call;
+type BalancesCall = polkadot::runtime_types::pallet_balances::pallet::Call;
+type DemocracyCall = polkadot::runtime_types::pallet_democracy::pallet::Call;
+type StakingCall = polkadot::runtime_types::pallet_staking::pallet::pallet::Call;
+type ProxyCall = polkadot::runtime_types::pallet_proxy::pallet::Call;
+type RewardDestination = polkadot::runtime_types::pallet_staking::RewardDestination<AccountId32>;
+
+/// Deposit reserved from an account with `nb_proxies` proxies.
+pub fn proxy_deposit(api: &OnlineClient<PolkadotConfig>, nb_proxies: usize) -> Result<u128, Box<dyn std::error::Error>> {
+    if nb_proxies == 0 {
+        return Ok(0);
+    }
+    let base = api.constants().at(&polkadot::constants().proxy().proxy_deposit_base())?;
+    let factor = api.constants().at(&polkadot::constants().proxy().proxy_deposit_factor())?;
+    Ok(base + factor * nb_proxies as u128)
+}
+
+/// Deposit reserved from a proxy with `nb_announcements` pending announcements.
+pub fn announcement_deposit(api: &OnlineClient<PolkadotConfig>, nb_announcements: usize) -> Result<u128, Box<dyn std::error::Error>> {
+    if nb_announcements == 0 {
+        return Ok(0);
+    }
+    let base = api.constants().at(&polkadot::constants().proxy().announcement_deposit_base())?;
+    let factor = api.constants().at(&polkadot::constants().proxy().announcement_deposit_factor())?;
+    Ok(base + factor * nb_announcements as u128)
+}
+
+/// Result of the call dispatched by a proxy, with the name of the error if it failed.
+fn proxy_result(api: &OnlineClient<PolkadotConfig>, events: &TxEvents<PolkadotConfig>) -> Result<Result<(), String>, Box<dyn std::error::Error>> {
+    let executed = events.find_first::<polkadot::proxy::events::ProxyExecuted>()?
+        .expect("ERROR: Proxy call not executed");
+    Ok(executed.result.map_err(|e| errors::dispatch_error_name(api, &e.encode())))
+}
+
+/// Has `delegate` dispatch `call` on behalf of `real`.
+async fn proxy_call(api: &OnlineClient<PolkadotConfig>, delegate: &sr25519::Pair, real: &AccountId32, call: Call) -> Result<(TxEvents<PolkadotConfig>, Result<(), String>), Box<dyn std::error::Error>> {
+    let events = submit(api, delegate, &polkadot::tx().proxy().proxy(real.clone(), None, call)).await?;
+    let result = proxy_result(api, &events)?;
+    Ok((events, result))
+}
+
+/// Adds proxies of every type for a test account, checks the calls each of them may dispatch
+/// and the deposits reserved for them, then removes them.
+pub async fn proxy_types(api: &OnlineClient<PolkadotConfig>, acc_seed_accounts : &[sr25519::Pair]) -> Result<(), Box<dyn std::error::Error>> {
+    let real = &acc_seed_accounts[27];
+    let real_id: AccountId32 = real.public().into();
+    let (any, non_transfer, governance, staking) = (&acc_seed_accounts[28], &acc_seed_accounts[29], &acc_seed_accounts[30], &acc_seed_accounts[31]);
+    let any_id: AccountId32 = any.public().into();
+    let delegates = [any, non_transfer, governance, staking];
+    let proxy_types = [ProxyType::Any, ProxyType::NonTransfer, ProxyType::Governance, ProxyType::Staking];
+    // Every proxy adds to the deposit of the delegating account.
+    let (previous, _) = api.storage().fetch_or_default(&polkadot::storage().proxy().proxies(&real_id), None).await?;
+    assert!(previous.0.is_empty(), "ERROR: Test account 27 already has proxies");
+    for (n, (delegate, proxy_type)) in delegates.iter().zip(proxy_types).enumerate() {
+        let delegate_id: AccountId32 = delegate.public().into();
+        let events = submit(api, real, &polkadot::tx().proxy().add_proxy(delegate_id.clone(), proxy_type, 0)).await?;
+        let added = events.find_first::<polkadot::proxy::events::ProxyAdded>()?
+            .expect("ERROR: Proxy not added");
+        assert_eq!((added.delegator, added.delegatee), (real_id.clone(), delegate_id));
+        let (_, reserved) = balances::balance_deltas(api, &real_id, events.block_hash()).await?;
+        assert_eq!(reserved, proxy_deposit(api, n + 1)? as i128 - proxy_deposit(api, n)? as i128);
+    }
+    let (proxies, deposit) = api.storage().fetch_or_default(&polkadot::storage().proxy().proxies(&real_id), None).await?;
+    assert_eq!((proxies.0.len(), deposit), (delegates.len(), proxy_deposit(api, delegates.len())?));
+    errors::assert_module_error(api, submit(api, real, &polkadot::tx().proxy().add_proxy(any_id.clone(), ProxyType::Any, 0)).await, "Duplicate");
+    errors::assert_module_error(api, submit(api, real, &polkadot::tx().proxy().add_proxy(real_id.clone(), ProxyType::Any, 0)).await, "NoSelfProxy");
+    println!("Proxies of every type added for test account 27, deposit {}",deposit);
+    // Each proxy type filters the calls it may dispatch.
+    let transfer = || Call::Balances(BalancesCall::transfer_keep_alive { dest: any_id.clone().into(), value: EDG });
+    let remark = || Call::System(SystemCall::remark { remark: b"remark through a proxy".to_vec() });
+    let number = blocks::block_number(api, None).await?;
+    let preimage = Call::Democracy(DemocracyCall::note_preimage { encoded_proposal: format!("preimage noted by a proxy at block {}", number).into_bytes() });
+    let set_payee = || Call::Staking(StakingCall::set_payee { payee: RewardDestination::Account(real_id.clone()) });
+    let (events, result) = proxy_call(api, any, &real_id, transfer()).await?;
+    assert_eq!(result, Ok(()));
+    let (free, _) = balances::balance_deltas(api, &real_id, events.block_hash()).await?;
+    assert_eq!(free, -(EDG as i128));
+    let (_, result) = proxy_call(api, non_transfer, &real_id, transfer()).await?;
+    assert_eq!(result, Err("CallFiltered".to_owned()));
+    let (_, result) = proxy_call(api, non_transfer, &real_id, remark()).await?;
+    assert_eq!(result, Ok(()));
+    let (events, result) = proxy_call(api, governance, &real_id, preimage).await?;
+    assert_eq!(result, Ok(()));
+    let noted = events.find_first::<polkadot::democracy::events::PreimageNoted>()?
+        .expect("ERROR: Preimage not noted");
+    assert_eq!(noted.who, real_id);
+    let (_, result) = proxy_call(api, governance, &real_id, transfer()).await?;
+    assert_eq!(result, Err("CallFiltered".to_owned()));
+    let (_, result) = proxy_call(api, governance, &real_id, set_payee()).await?;
+    assert_eq!(result, Err("CallFiltered".to_owned()));
+    let (_, result) = proxy_call(api, staking, &real_id, set_payee()).await?;
+    assert_eq!(result, Ok(()));
+    let (_, result) = proxy_call(api, staking, &real_id, remark()).await?;
+    assert_eq!(result, Err("CallFiltered".to_owned()));
+    errors::assert_module_error(api, submit(api, staking, &polkadot::tx().proxy().proxy(real_id.clone(), Some(ProxyType::Any), remark())).await, "NotProxy");
+    println!("Calls of test account 27 filtered by proxy type");
+    // Removing proxies releases their deposit.
+    let events = submit(api, real, &polkadot::tx().proxy().remove_proxy(staking.public().into(), ProxyType::Staking, 0)).await?;
+    assert!(events.has::<polkadot::proxy::events::ProxyRemoved>()?);
+    let (_, reserved) = balances::balance_deltas(api, &real_id, events.block_hash()).await?;
+    assert_eq!(reserved, proxy_deposit(api, delegates.len() - 1)? as i128 - proxy_deposit(api, delegates.len())? as i128);
+    errors::assert_module_error(api, submit(api, staking, &polkadot::tx().proxy().proxy(real_id.clone(), None, set_payee())).await, "NotProxy");
+    let events = submit(api, real, &polkadot::tx().proxy().remove_proxies()).await?;
+    let (_, reserved) = balances::balance_deltas(api, &real_id, events.block_hash()).await?;
+    assert_eq!(reserved, -(proxy_deposit(api, delegates.len() - 1)? as i128));
+    assert!(api.storage().fetch(&polkadot::storage().proxy().proxies(&real_id), Some(events.block_hash())).await?.is_none());
+    println!("Proxies of test account 27 removed");
+    Ok(())
+}
+
+/// Has a delayed proxy announce calls, checking they can only be dispatched once the delay is
+/// over and the deposits reserved for the announcements.
+pub async fn announced_proxy(api: &OnlineClient<PolkadotConfig>, acc_seed_accounts : &[sr25519::Pair]) -> Result<(), Box<dyn std::error::Error>> {
+    let (real, delegate, relayer) = (&acc_seed_accounts[27], &acc_seed_accounts[32], &acc_seed_accounts[28]);
+    let real_id: AccountId32 = real.public().into();
+    let delegate_id: AccountId32 = delegate.public().into();
+    let delay = 2;
+    submit(api, real, &polkadot::tx().proxy().add_proxy(delegate_id.clone(), ProxyType::Any, delay)).await?;
+    let remark = |text: &str| Call::System(SystemCall::remark { remark: text.as_bytes().to_vec() });
+    // Delayed proxies can only dispatch announced calls.
+    errors::assert_module_error(api, submit(api, delegate, &polkadot::tx().proxy().proxy(real_id.clone(), None, remark("unannounced"))).await, "Unannounced");
+    let call_hash = BlakeTwo256::hash_of(&remark("announced"));
+    let events = submit(api, delegate, &polkadot::tx().proxy().announce(real_id.clone(), call_hash)).await?;
+    let announced = events.find_first::<polkadot::proxy::events::Announced>()?
+        .expect("ERROR: Call not announced");
+    assert_eq!((announced.real, announced.proxy, announced.call_hash), (real_id.clone(), delegate_id.clone(), call_hash));
+    let (_, reserved) = balances::balance_deltas(api, &delegate_id, events.block_hash()).await?;
+    assert_eq!(reserved, announcement_deposit(api, 1)? as i128);
+    let rejected_hash = BlakeTwo256::hash_of(&remark("rejected"));
+    let events = submit(api, delegate, &polkadot::tx().proxy().announce(real_id.clone(), rejected_hash)).await?;
+    let (_, reserved) = balances::balance_deltas(api, &delegate_id, events.block_hash()).await?;
+    assert_eq!(reserved, announcement_deposit(api, 2)? as i128 - announcement_deposit(api, 1)? as i128);
+    // The delegating account can reject an announcement.
+    let events = submit(api, real, &polkadot::tx().proxy().reject_announcement(delegate_id.clone(), rejected_hash)).await?;
+    let (_, reserved) = balances::balance_deltas(api, &delegate_id, events.block_hash()).await?;
+    assert_eq!(reserved, announcement_deposit(api, 1)? as i128 - announcement_deposit(api, 2)? as i128);
+    errors::assert_module_error(api, submit(api, relayer, &polkadot::tx().proxy().proxy_announced(delegate_id.clone(), real_id.clone(), None, remark("rejected"))).await, "Unannounced");
+    // Anybody can dispatch an announced call once the delay is over.
+    let (announcements, _) = api.storage().fetch_or_default(&polkadot::storage().proxy().announcements(&delegate_id), None).await?;
+    let height = announcements.0.iter().find(|a| a.call_hash == call_hash)
+        .expect("ERROR: Announcement not registered").height;
+    while blocks::block_number(api, None).await? < height + delay {
+        blocks::wait_blocks(api, 1).await?;
+    }
+    let events = submit(api, relayer, &polkadot::tx().proxy().proxy_announced(delegate_id.clone(), real_id.clone(), None, remark("announced"))).await?;
+    assert_eq!(proxy_result(api, &events)?, Ok(()));
+    let (_, reserved) = balances::balance_deltas(api, &delegate_id, events.block_hash()).await?;
+    assert_eq!(reserved, -(announcement_deposit(api, 1)? as i128));
+    assert!(api.storage().fetch(&polkadot::storage().proxy().announcements(&delegate_id), Some(events.block_hash())).await?.is_none());
+    submit(api, real, &polkadot::tx().proxy().remove_proxies()).await?;
+    println!("Announced call of test account 27 dispatched after {} blocks",delay);
+    Ok(())
+}
+
+/// Creates an anonymous proxied account, uses it and has it kill itself.
+pub async fn anonymous_proxy(api: &OnlineClient<PolkadotConfig>, acc_seed_accounts : &[sr25519::Pair]) -> Result<(), Box<dyn std::error::Error>> {
+    let spawner = &acc_seed_accounts[33];
+    let spawner_id: AccountId32 = spawner.public().into();
+    let index = 0;
+    let events = submit(api, spawner, &polkadot::tx().proxy().anonymous(ProxyType::Any, 0, index)).await?;
+    let created = events.find_first::<polkadot::proxy::events::AnonymousCreated>()?
+        .expect("ERROR: Anonymous proxy not created");
+    assert_eq!((created.who.clone(), created.disambiguation_index), (spawner_id.clone(), index));
+    let anonymous = created.anonymous;
+    let (_, reserved) = balances::balance_deltas(api, &spawner_id, events.block_hash()).await?;
+    assert_eq!(reserved, proxy_deposit(api, 1)? as i128);
+    let (proxies, deposit) = api.storage().fetch_or_default(&polkadot::storage().proxy().proxies(&anonymous), Some(events.block_hash())).await?;
+    assert_eq!(deposit, proxy_deposit(api, 1)?);
+    assert!(proxies.0.len() == 1 && proxies.0[0].delegate == spawner_id && proxies.0[0].delay == 0);
+    // The anonymous account is killed with the block and extrinsic which created it.
+    let height = blocks::block_number(api, Some(events.block_hash())).await?;
+    let block = api.rpc().block(Some(events.block_hash())).await?
+        .expect("the block has been imported; qed");
+    let ext_index = block.block.extrinsics.iter().position(|e| BlakeTwo256::hash_of(e) == events.extrinsic_hash())
+        .expect("the extrinsic is in its block; qed") as u32;
+    submit(api, spawner, &polkadot::tx().balances().transfer(anonymous.clone().into(), 10 * EDG)).await?;
+    let transfer = Call::Balances(BalancesCall::transfer { dest: spawner_id.clone().into(), value: EDG });
+    let (events, result) = proxy_call(api, spawner, &anonymous, transfer).await?;
+    assert_eq!(result, Ok(()));
+    let (free, _) = balances::balance_deltas(api, &anonymous, events.block_hash()).await?;
+    assert_eq!(free, -(EDG as i128));
+    let kill = Call::Proxy(ProxyCall::kill_anonymous { spawner: spawner_id.clone(), proxy_type: ProxyType::Any, index, height, ext_index });
+    let (events, result) = proxy_call(api, spawner, &anonymous, kill).await?;
+    assert_eq!(result, Ok(()));
+    let (_, reserved) = balances::balance_deltas(api, &spawner_id, events.block_hash()).await?;
+    assert_eq!(reserved, -(proxy_deposit(api, 1)? as i128));
+    assert!(api.storage().fetch(&polkadot::storage().proxy().proxies(&anonymous), Some(events.block_hash())).await?.is_none());
+    let transfer = Call::Balances(BalancesCall::transfer { dest: spawner_id.clone().into(), value: EDG });
+    errors::assert_module_error(api, submit(api, spawner, &polkadot::tx().proxy().proxy(anonymous.clone(), None, transfer)).await, "NotProxy");
+    println!("Anonymous proxy {} of test account 33 created and killed",anonymous);
+    Ok(())
+}
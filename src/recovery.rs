@@ -0,0 +1,125 @@
+use subxt::{
+    ext::{
+        sp_core::{sr25519, Pair},
+        sp_runtime::AccountId32,
+    },
+    OnlineClient,
+    PolkadotConfig,
+};
+use crate::consts::*;
+use crate::{balances, blocks, errors};
+use crate::tx::submit;
+
+#[subxt::subxt(runtime_metadata_path = "./data/metadata.scale")]
+pub mod polkadot {}
+
+type Call = polkadot::runtime_types::edgeware_runtime::Call;
+type BalancesCall = polkadot::runtime_types::pallet_balances::pallet::Call;
+type RecoveryCall = polkadot::runtime_types::pallet_recovery::pallet::Call;
+
+/// Deposit reserved by an account making itself recoverable by `nb_friends` friends.
+fn config_deposit(api: &OnlineClient<PolkadotConfig>, nb_friends: usize) -> Result<u128, Box<dyn std::error::Error>> {
+    let base = api.constants().at(&polkadot::constants().recovery().config_deposit_base())?;
+    let factor = api.constants().at(&polkadot::constants().recovery().friend_deposit_factor())?;
+    Ok(base + factor * nb_friends as u128)
+}
+
+/// Has a test account made recoverable by three friends recovered by another one once two
+/// friends vouched and the delay passed, then has the rescuer move funds of the recovered
+/// account, close the recovery and remove the recovery configuration, checking the deposits.
+pub async fn social_recovery(api: &OnlineClient<PolkadotConfig>, acc_seed_accounts : &[sr25519::Pair]) -> Result<(), Box<dyn std::error::Error>> {
+    let (lost, rescuer, outsider) = (&acc_seed_accounts[49], &acc_seed_accounts[50], &acc_seed_accounts[54]);
+    let lost_id: AccountId32 = lost.public().into();
+    let rescuer_id: AccountId32 = rescuer.public().into();
+    let mut friends: Vec<(AccountId32, &sr25519::Pair)> = acc_seed_accounts[51..54].iter()
+        .map(|pair| (pair.public().into(), pair))
+        .collect();
+    friends.sort_by(|(a, _), (b, _)| a.cmp(b));
+    let friend_ids: Vec<AccountId32> = friends.iter().map(|(id, _)| id.clone()).collect();
+    let (threshold, delay_period) = (2, 20);
+    let recovery_deposit = api.constants().at(&polkadot::constants().recovery().recovery_deposit())?;
+    let config_deposit = config_deposit(api, friend_ids.len())?;
+    // Making an account recoverable reserves the base deposit and a deposit per friend.
+    let unsorted: Vec<AccountId32> = friend_ids.iter().rev().cloned().collect();
+    errors::assert_module_error(api, submit(api, lost, &polkadot::tx().recovery().create_recovery(unsorted, threshold, delay_period)).await, "NotSorted");
+    let events = submit(api, lost, &polkadot::tx().recovery().create_recovery(friend_ids.clone(), threshold, delay_period)).await?;
+    let created = events.find_first::<polkadot::recovery::events::RecoveryCreated>()?
+        .expect("ERROR: Recovery not created");
+    assert_eq!(created.account, lost_id);
+    let (_, reserved) = balances::balance_deltas(api, &lost_id, events.block_hash()).await?;
+    assert_eq!(reserved, config_deposit as i128);
+    let config = api.storage().fetch(&polkadot::storage().recovery().recoverable(&lost_id), Some(events.block_hash())).await?
+        .expect("ERROR: Recovery configuration not stored");
+    assert_eq!((config.delay_period, config.deposit, config.friends.0, config.threshold), (delay_period, config_deposit, friend_ids.clone(), threshold));
+    errors::assert_module_error(api, submit(api, lost, &polkadot::tx().recovery().create_recovery(friend_ids.clone(), threshold, delay_period)).await, "AlreadyRecoverable");
+    println!("Test account 49 recoverable by {} of its {} friends",threshold, friend_ids.len());
+    // Initiating a recovery reserves the recovery deposit of the rescuer.
+    let events = submit(api, rescuer, &polkadot::tx().recovery().initiate_recovery(lost_id.clone())).await?;
+    let initiated = events.find_first::<polkadot::recovery::events::RecoveryInitiated>()?
+        .expect("ERROR: Recovery not initiated");
+    assert_eq!((initiated.lost_account, initiated.rescuer_account), (lost_id.clone(), rescuer_id.clone()));
+    let (_, reserved) = balances::balance_deltas(api, &rescuer_id, events.block_hash()).await?;
+    assert_eq!(reserved, recovery_deposit as i128);
+    let initiated_at = blocks::block_number(api, Some(events.block_hash())).await?;
+    let active = api.storage().fetch(&polkadot::storage().recovery().active_recoveries(&lost_id, &rescuer_id), Some(events.block_hash())).await?
+        .expect("ERROR: Active recovery not stored");
+    assert_eq!((active.created, active.deposit, active.friends.0.len()), (initiated_at, recovery_deposit, 0));
+    errors::assert_module_error(api, submit(api, rescuer, &polkadot::tx().recovery().initiate_recovery(lost_id.clone())).await, "AlreadyStarted");
+    errors::assert_module_error(api, submit(api, rescuer, &polkadot::tx().recovery().claim_recovery(lost_id.clone())).await, "DelayPeriod");
+    // Only friends vouch, each of them once.
+    errors::assert_module_error(api, submit(api, rescuer, &polkadot::tx().recovery().vouch_recovery(lost_id.clone(), rescuer_id.clone())).await, "NotFriend");
+    let events = submit(api, friends[0].1, &polkadot::tx().recovery().vouch_recovery(lost_id.clone(), rescuer_id.clone())).await?;
+    let vouched = events.find_first::<polkadot::recovery::events::RecoveryVouched>()?
+        .expect("ERROR: Recovery not vouched");
+    assert_eq!((vouched.lost_account, vouched.rescuer_account, vouched.sender), (lost_id.clone(), rescuer_id.clone(), friends[0].0.clone()));
+    errors::assert_module_error(api, submit(api, friends[0].1, &polkadot::tx().recovery().vouch_recovery(lost_id.clone(), rescuer_id.clone())).await, "AlreadyVouched");
+    // The account is recovered once the delay passed and enough friends vouched.
+    blocks::wait_for_block(api, initiated_at + delay_period).await?;
+    errors::assert_module_error(api, submit(api, rescuer, &polkadot::tx().recovery().claim_recovery(lost_id.clone())).await, "Threshold");
+    submit(api, friends[1].1, &polkadot::tx().recovery().vouch_recovery(lost_id.clone(), rescuer_id.clone())).await?;
+    let events = submit(api, rescuer, &polkadot::tx().recovery().claim_recovery(lost_id.clone())).await?;
+    let recovered = events.find_first::<polkadot::recovery::events::AccountRecovered>()?
+        .expect("ERROR: Account not recovered");
+    assert_eq!((recovered.lost_account, recovered.rescuer_account), (lost_id.clone(), rescuer_id.clone()));
+    let proxy = api.storage().fetch(&polkadot::storage().recovery().proxy(&rescuer_id), Some(events.block_hash())).await?;
+    assert_eq!(proxy, Some(lost_id.clone()));
+    println!("Test account 49 recovered by test account 50 after {} vouches",threshold);
+    // The rescuer dispatches calls on behalf of the recovered account.
+    let transfer = Call::Balances(BalancesCall::transfer { dest: rescuer_id.clone().into(), value: 10 * EDG });
+    let events = submit(api, rescuer, &polkadot::tx().recovery().as_recovered(lost_id.clone(), transfer)).await?;
+    let transferred = events.find_first::<polkadot::balances::events::Transfer>()?
+        .expect("ERROR: Recovered call not dispatched");
+    assert_eq!((transferred.from, transferred.to, transferred.amount), (lost_id.clone(), rescuer_id.clone(), 10 * EDG));
+    let (free, _) = balances::balance_deltas(api, &lost_id, events.block_hash()).await?;
+    assert_eq!(free, -(10 * EDG as i128));
+    let transfer = Call::Balances(BalancesCall::transfer { dest: rescuer_id.clone().into(), value: 10 * EDG });
+    errors::assert_module_error(api, submit(api, outsider, &polkadot::tx().recovery().as_recovered(lost_id.clone(), transfer)).await, "NotAllowed");
+    println!("Test account 50 transferred funds of recovered test account 49");
+    // The configuration can not be removed while a recovery is active.
+    let remove = || Call::Recovery(RecoveryCall::remove_recovery);
+    errors::assert_module_error(api, submit(api, rescuer, &polkadot::tx().recovery().as_recovered(lost_id.clone(), remove())).await, "StillActive");
+    // Closing the recovery hands the deposit of the rescuer to the recovered account.
+    let close = Call::Recovery(RecoveryCall::close_recovery { rescuer: rescuer_id.clone() });
+    let events = submit(api, rescuer, &polkadot::tx().recovery().as_recovered(lost_id.clone(), close)).await?;
+    let closed = events.find_first::<polkadot::recovery::events::RecoveryClosed>()?
+        .expect("ERROR: Recovery not closed");
+    assert_eq!((closed.lost_account, closed.rescuer_account), (lost_id.clone(), rescuer_id.clone()));
+    let (_, reserved) = balances::balance_deltas(api, &rescuer_id, events.block_hash()).await?;
+    assert_eq!(reserved, -(recovery_deposit as i128));
+    let (free, _) = balances::balance_deltas(api, &lost_id, events.block_hash()).await?;
+    assert_eq!(free, recovery_deposit as i128);
+    assert!(api.storage().fetch(&polkadot::storage().recovery().active_recoveries(&lost_id, &rescuer_id), Some(events.block_hash())).await?.is_none());
+    // Removing the configuration returns the config deposit.
+    let events = submit(api, rescuer, &polkadot::tx().recovery().as_recovered(lost_id.clone(), remove())).await?;
+    let removed = events.find_first::<polkadot::recovery::events::RecoveryRemoved>()?
+        .expect("ERROR: Recovery configuration not removed");
+    assert_eq!(removed.lost_account, lost_id);
+    let (free, reserved) = balances::balance_deltas(api, &lost_id, events.block_hash()).await?;
+    assert_eq!((free, reserved), (config_deposit as i128, -(config_deposit as i128)));
+    assert!(api.storage().fetch(&polkadot::storage().recovery().recoverable(&lost_id), Some(events.block_hash())).await?.is_none());
+    // The rescuer gives up its access to the recovered account.
+    let events = submit(api, rescuer, &polkadot::tx().recovery().cancel_recovered(lost_id.clone())).await?;
+    assert!(api.storage().fetch(&polkadot::storage().recovery().proxy(&rescuer_id), Some(events.block_hash())).await?.is_none());
+    println!("Recovery of test account 49 closed and removed");
+    Ok(())
+}
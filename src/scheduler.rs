@@ -0,0 +1,143 @@
+use codec::Encode;
+use subxt::{
+    ext::{
+        sp_core::{sr25519, Pair, H256},
+        sp_runtime::AccountId32,
+    },
+    OnlineClient,
+    PolkadotConfig,
+};
+use crate::consts::*;
+use crate::{balances, blocks};
+use crate::tx::{submit, sudo_result};
+
+#[subxt::subxt(runtime_metadata_path = "./data/metadata.scale")]
+pub mod polkadot {}
+
+type Call = polkadot::runtime_types::edgeware_runtime::Call;
+type SchedulerCall = polkadot::runtime_types::pallet_scheduler::pallet::Call;
+type BalancesCall = polkadot::runtime_types::pallet_balances::pallet::Call;
+type MaybeHashed = polkadot::runtime_types::frame_support::traits::schedule::MaybeHashed<Call, H256>;
+
+/// Name under which democracy schedules the enactment of a referendum.
+pub fn enactment_id(ref_index: u32) -> Vec<u8> {
+    (b"democrac", ref_index).encode()
+}
+
+/// Schedules `call` as root, returning the block and index of the scheduled task.
+async fn schedule(api: &OnlineClient<PolkadotConfig>, sudo_seed_account: &sr25519::Pair, call: SchedulerCall) -> Result<(u32, u32), Box<dyn std::error::Error>> {
+    let (events, result) = sudo_result(api, sudo_seed_account, Call::Scheduler(call)).await?;
+    assert_eq!(result, Ok(()), "ERROR: Call not scheduled");
+    let scheduled = events.find_first::<polkadot::scheduler::events::Scheduled>()?
+        .expect("ERROR: Call not scheduled");
+    let agenda = api.storage().fetch_or_default(&polkadot::storage().scheduler().agenda(&scheduled.when), Some(events.block_hash())).await?;
+    assert!(matches!(agenda.get(scheduled.index as usize), Some(Some(_))), "ERROR: Scheduled call not in the agenda");
+    Ok((scheduled.when, scheduled.index))
+}
+
+/// Checks a task was dispatched successfully in block `when` and returns the hash of the block.
+async fn check_dispatched(api: &OnlineClient<PolkadotConfig>, when: u32, id: Option<&[u8]>) -> Result<H256, Box<dyn std::error::Error>> {
+    let hash = blocks::wait_for_block(api, when).await?;
+    let events = api.events().at(Some(hash)).await?;
+    let mut dispatched = None;
+    for event in events.find::<polkadot::scheduler::events::Dispatched>() {
+        let event = event?;
+        if event.task.0 == when && event.id.as_deref() == id {
+            dispatched = Some(event);
+        }
+    }
+    let dispatched = dispatched.unwrap_or_else(|| panic!("ERROR: Scheduled call not dispatched at block {}", when));
+    assert!(dispatched.result.is_ok(), "ERROR: Scheduled call failed: {:?}", dispatched.result);
+    Ok(hash)
+}
+
+/// Root transfer between test accounts, whose effect is checked when dispatched.
+fn force_transfer(source: &AccountId32, dest: &AccountId32) -> Box<MaybeHashed> {
+    Box::new(MaybeHashed::Value(Call::Balances(BalancesCall::force_transfer { source: source.clone().into(), dest: dest.clone().into(), value: EDG })))
+}
+
+/// Has root schedule one-off, periodic and named transfers between test accounts, checking they
+/// are dispatched at the expected blocks, and cancels scheduled calls by name and by index.
+pub async fn scheduler_lifecycle(api: &OnlineClient<PolkadotConfig>, acc_seed_accounts : &[sr25519::Pair], sudo_seed_account: &sr25519::Pair) -> Result<(), Box<dyn std::error::Error>> {
+    let source: AccountId32 = acc_seed_accounts[45].public().into();
+    let dest: AccountId32 = acc_seed_accounts[46].public().into();
+    // A call is dispatched in the block it is scheduled for.
+    let number = blocks::block_number(api, None).await?;
+    let (when, _) = schedule(api, sudo_seed_account, SchedulerCall::schedule { when: number + 5, maybe_periodic: None, priority: 0, call: force_transfer(&source, &dest) }).await?;
+    assert_eq!(when, number + 5);
+    let hash = check_dispatched(api, when, None).await?;
+    let parent = blocks::parent_hash(api, hash).await?;
+    let (free_before, ..) = balances::account_data(api, &dest, Some(parent)).await?;
+    let (free_after, ..) = balances::account_data(api, &dest, Some(hash)).await?;
+    assert_eq!(free_after - free_before, EDG);
+    println!("Scheduled call dispatched at block {}",when);
+    // Calls can not be scheduled in the past.
+    let (_, result) = sudo_result(api, sudo_seed_account, Call::Scheduler(SchedulerCall::schedule { when: 1, maybe_periodic: None, priority: 0, call: force_transfer(&source, &dest) })).await?;
+    assert_eq!(result, Err("TargetBlockNumberInPast".to_owned()));
+    // `schedule_after` counts from the block after the one scheduling the call.
+    let (events, result) = sudo_result(api, sudo_seed_account, Call::Scheduler(SchedulerCall::schedule_after { after: 3, maybe_periodic: None, priority: 0, call: force_transfer(&source, &dest) })).await?;
+    assert_eq!(result, Ok(()));
+    let scheduled = events.find_first::<polkadot::scheduler::events::Scheduled>()?
+        .expect("ERROR: Call not scheduled");
+    assert_eq!(scheduled.when, blocks::block_number(api, Some(events.block_hash())).await? + 4);
+    check_dispatched(api, scheduled.when, None).await?;
+    println!("Call scheduled after 3 blocks dispatched at block {}",scheduled.when);
+    // Periodic calls are dispatched every period, as many times as requested.
+    let id = b"periodic transfer".to_vec();
+    let number = blocks::block_number(api, None).await?;
+    let (period, count) = (2, 3);
+    let (when, _) = schedule(api, sudo_seed_account, SchedulerCall::schedule_named { id: id.clone(), when: number + 5, maybe_periodic: Some((period, count)), priority: 0, call: force_transfer(&source, &dest) }).await?;
+    for i in 0..count {
+        check_dispatched(api, when + i * period, Some(&id)).await?;
+    }
+    let hash = blocks::wait_for_block(api, when + (count - 1) * period).await?;
+    assert!(api.storage().fetch(&polkadot::storage().scheduler().lookup(&id), Some(hash)).await?.is_none());
+    println!("Periodic call dispatched {} times from block {}",count, when);
+    // Named calls are canceled by name, others by block and index.
+    let id = b"canceled transfer".to_vec();
+    let number = blocks::block_number(api, None).await?;
+    let (when, index) = schedule(api, sudo_seed_account, SchedulerCall::schedule_named { id: id.clone(), when: number + 20, maybe_periodic: None, priority: 0, call: force_transfer(&source, &dest) }).await?;
+    let lookup = api.storage().fetch(&polkadot::storage().scheduler().lookup(&id), None).await?;
+    assert_eq!(lookup, Some((when, index)));
+    let (events, result) = sudo_result(api, sudo_seed_account, Call::Scheduler(SchedulerCall::cancel_named { id: id.clone() })).await?;
+    assert_eq!(result, Ok(()));
+    let canceled = events.find_first::<polkadot::scheduler::events::Canceled>()?
+        .expect("ERROR: Named call not canceled");
+    assert_eq!((canceled.when, canceled.index), (when, index));
+    assert!(api.storage().fetch(&polkadot::storage().scheduler().lookup(&id), Some(events.block_hash())).await?.is_none());
+    let (_, result) = sudo_result(api, sudo_seed_account, Call::Scheduler(SchedulerCall::cancel_named { id })).await?;
+    assert_eq!(result, Err("NotFound".to_owned()));
+    let (when, index) = schedule(api, sudo_seed_account, SchedulerCall::schedule { when: number + 20, maybe_periodic: None, priority: 0, call: force_transfer(&source, &dest) }).await?;
+    let (events, result) = sudo_result(api, sudo_seed_account, Call::Scheduler(SchedulerCall::cancel { when, index })).await?;
+    assert_eq!(result, Ok(()));
+    assert!(events.has::<polkadot::scheduler::events::Canceled>()?);
+    let agenda = api.storage().fetch_or_default(&polkadot::storage().scheduler().agenda(&when), Some(events.block_hash())).await?;
+    assert!(matches!(agenda.get(index as usize), None | Some(None)));
+    // Nothing is dispatched for the canceled calls.
+    let hash = blocks::wait_for_block(api, when).await?;
+    let events = api.events().at(Some(hash)).await?;
+    for event in events.find::<polkadot::scheduler::events::Dispatched>() {
+        assert!(event?.task != (when, index), "ERROR: Canceled call dispatched");
+    }
+    println!("Scheduled calls canceled by name and index");
+    Ok(())
+}
+
+/// Checks the referendum enacted in the block with the given hash passed `EnactmentPeriod` blocks
+/// earlier, and was dispatched by the scheduler under its democracy name.
+pub async fn verify_enactment(api: &OnlineClient<PolkadotConfig>, ref_index: u32, hash: H256) -> Result<(), Box<dyn std::error::Error>> {
+    let enactment_period = api.constants().at(&polkadot::constants().democracy().enactment_period())?;
+    let number = blocks::block_number(api, Some(hash)).await?;
+    check_dispatched(api, number, Some(&enactment_id(ref_index))).await?;
+    let passed_hash = api.rpc().block_hash(Some((number - enactment_period).into())).await?
+        .expect("the block has been finalized; qed");
+    let events = api.events().at(Some(passed_hash)).await?;
+    let passed = events.find::<polkadot::democracy::events::Passed>()
+        .any(|e| matches!(e, Ok(e) if e.ref_index == ref_index));
+    assert!(passed, "ERROR: Referendum {} not enacted {} blocks after passing", ref_index, enactment_period);
+    let scheduled = events.find::<polkadot::scheduler::events::Scheduled>()
+        .any(|e| matches!(e, Ok(e) if e.when == number));
+    assert!(scheduled, "ERROR: Enactment of referendum {} not scheduled", ref_index);
+    println!("Referendum {} enacted at block {}, {} blocks after passing",ref_index, number, enactment_period);
+    Ok(())
+}
@@ -1,4 +1,6 @@
-use std::cmp::max;
+use std::cmp::{max, min};
+use std::collections::HashMap;
+use codec::{Decode, Encode};
 use subxt::{
     tx::{
         Era,
@@ -7,51 +9,856 @@ use subxt::{
         PolkadotExtrinsicParamsBuilder as Params,
     },
     ext::{
-        sp_core::{sr25519, Pair},
+        sp_core::{sr25519, Pair, H256},
         sp_runtime::AccountId32,
     },
     OnlineClient,
     PolkadotConfig,
 };
+use serde::Deserialize;
+use rand::Rng;
 use crate::consts::*;
+use crate::{balances, blocks, errors};
+use crate::tx::{submit, sudo};
 
 #[subxt::subxt(runtime_metadata_path = "./data/metadata.scale")]
 pub mod polkadot {}
 
-pub async fn nominate_all(api: &OnlineClient<PolkadotConfig>, acc_seed_accounts : &[sr25519::Pair]) -> Result<(), Box<dyn std::error::Error>> {
-    // Nominate all to the first active validator.
-    let validators_addr = polkadot::storage().session().validators();
-    let validators = api.storage().fetch(&validators_addr, None).await?.unwrap();
-    if 0<validators.len() {
-        // Bond tokens
-        for i in 0..NB_TEST_ACCOUNTS {
-            let acc_seed_account_id: AccountId32 = acc_seed_accounts[i as usize].public().into();
-            let tx = polkadot::tx().staking().bond(
-                acc_seed_account_id.clone().into(),
-                max(EXISTENTIAL_DEPOSIT, TEST_ACCOUNT_FUNDING / 10 - 10 * EDG - EXISTENTIAL_DEPOSIT * (NB_TEST_ACCOUNTS - 1 - i) as u128),
-                polkadot::runtime_types::pallet_staking::RewardDestination::Account(acc_seed_account_id.into()),
-            );
-            let tx_params = Params::new()
-                .tip(PlainTip::new(0))
-                .era(Era::Immortal, api.genesis_hash());
-            let acc_signer = PairSigner::new(acc_seed_accounts[i as usize].clone());
-            // submit the transaction:
-            let hash = api.tx().sign_and_submit(&tx, &acc_signer, tx_params).await?;
-            println!("Bonding extrinsic submitted for test account {}: {}",i, hash);
-        }
-        // Nominate
-        for i in 0..NB_TEST_ACCOUNTS {
-            let tx = polkadot::tx().staking().nominate(
-                vec![validators[0].clone().into()]
-            );
-            let tx_params = Params::new()
-                .tip(PlainTip::new(0))
-                .era(Era::Immortal, api.genesis_hash());
-            let acc_signer = PairSigner::new(acc_seed_accounts[i as usize].clone());
-            // submit the transaction:
-            let hash = api.tx().sign_and_submit(&tx, &acc_signer, tx_params).await?;
-            println!("Nominating extrinsic submitted for test account {}: {}",i, hash);
-        }
-    }
-    Ok(())
-}
\ No newline at end of file
+type RewardDestination = polkadot::runtime_types::pallet_staking::RewardDestination<AccountId32>;
+type Exposure = polkadot::runtime_types::pallet_staking::Exposure<AccountId32, u128>;
+type ValidatorPrefs = polkadot::runtime_types::pallet_staking::ValidatorPrefs;
+type Perbill = polkadot::runtime_types::sp_arithmetic::per_things::Perbill;
+type Percent = polkadot::runtime_types::sp_arithmetic::per_things::Percent;
+type ConfigOp<T> = polkadot::runtime_types::pallet_staking::pallet::pallet::ConfigOp<T>;
+type SessionKeys = polkadot::runtime_types::edgeware_runtime::SessionKeys;
+type Call = polkadot::runtime_types::edgeware_runtime::Call;
+type StakingCall = polkadot::runtime_types::pallet_staking::pallet::pallet::Call;
+type Forcing = polkadot::runtime_types::pallet_staking::Forcing;
+type UnappliedSlash = polkadot::runtime_types::pallet_staking::UnappliedSlash<AccountId32, u128>;
+
+/// Amount bonded by test account `i`, distinct for every account.
+pub fn bond_amount(i: u32) -> u128 {
+    max(EXISTENTIAL_DEPOSIT, TEST_ACCOUNT_FUNDING / 10 - 10 * EDG - EXISTENTIAL_DEPOSIT * (NB_TEST_ACCOUNTS - 1 - i) as u128)
+}
+
+/// How the test accounts pick the validators they nominate.
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub enum NominationStrategy {
+    /// Every account nominates the first active validator.
+    First,
+    /// Accounts nominate the validators in turn, spreading the stake over the whole validator set.
+    Spread,
+    /// Every account nominates a random subset of up to `MaxNominations` validators.
+    RandomSubsets,
+    /// Every account nominates the validators waiting to enter the active set, or the first
+    /// active validator if none is waiting.
+    Waiting,
+}
+
+/// Accounts of all the validators, active or waiting.
+pub async fn validator_candidates(api: &OnlineClient<PolkadotConfig>) -> Result<Vec<AccountId32>, Box<dyn std::error::Error>> {
+    let mut iter = api.storage().iter(polkadot::storage().staking().validators_root(), 10, None).await?;
+    let mut validators = Vec::new();
+    while let Some((key, _)) = iter.next().await? {
+        // The key ends with the account, hashed with `Twox64Concat`.
+        let raw: [u8; 32] = key.0[key.0.len() - 32..].try_into().expect("the key ends with an account id; qed");
+        validators.push(AccountId32::from(raw));
+    }
+    Ok(validators)
+}
+
+/// Validators nominated by every test account according to `strategy`.
+async fn nomination_targets(api: &OnlineClient<PolkadotConfig>, strategy: NominationStrategy) -> Result<Vec<Vec<AccountId32>>, Box<dyn std::error::Error>> {
+    let active = api.storage().fetch_or_default(&polkadot::storage().session().validators(), None).await?;
+    let mut all = validator_candidates(api).await?;
+    for v in active.iter() {
+        if !all.contains(v) {
+            all.push(v.clone());
+        }
+    }
+    all.sort();
+    let waiting: Vec<AccountId32> = all.iter().filter(|v| !active.contains(v)).cloned().collect();
+    let max_nominations = api.constants().at(&polkadot::constants().staking().max_nominations())? as usize;
+    let strategy = match strategy {
+        NominationStrategy::Waiting if waiting.is_empty() => {
+            println!("### No waiting validator, nominating with the First strategy instead ###");
+            NominationStrategy::First
+        },
+        strategy => strategy,
+    };
+    let mut rng = rand::thread_rng();
+    Ok((0..NB_TEST_ACCOUNTS as usize).map(|i| match strategy {
+        NominationStrategy::First => active.iter().take(1).cloned().collect(),
+        NominationStrategy::Spread if !all.is_empty() => vec![all[i % all.len()].clone()],
+        NominationStrategy::RandomSubsets if !all.is_empty() => {
+            let nb = rng.gen_range(1..=min(max_nominations, all.len()));
+            rand::seq::index::sample(&mut rng, all.len(), nb).into_iter().map(|k| all[k].clone()).collect()
+        },
+        NominationStrategy::Waiting => waiting.iter().take(max_nominations).cloned().collect(),
+        _ => vec![],
+    }).collect())
+}
+
+/// Bonds every test account and has it nominate validators according to `strategy`.
+/// Returns the validators nominated by each account.
+pub async fn nominate_all(api: &OnlineClient<PolkadotConfig>, acc_seed_accounts : &[sr25519::Pair], strategy: NominationStrategy) -> Result<Vec<Vec<AccountId32>>, Box<dyn std::error::Error>> {
+    let targets = nomination_targets(api, strategy).await?;
+    if targets.iter().all(|t| t.is_empty()) {
+        println!("### No validator to nominate with the {:?} strategy ###",strategy);
+        return Ok(targets);
+    }
+    // Bond tokens
+    for i in 0..NB_TEST_ACCOUNTS {
+        let acc_seed_account_id: AccountId32 = acc_seed_accounts[i as usize].public().into();
+        let tx = polkadot::tx().staking().bond(
+            acc_seed_account_id.clone().into(),
+            bond_amount(i),
+            polkadot::runtime_types::pallet_staking::RewardDestination::Account(acc_seed_account_id.into()),
+        );
+        let tx_params = Params::new()
+            .tip(PlainTip::new(0))
+            .era(Era::Immortal, api.genesis_hash());
+        let acc_signer = PairSigner::new(acc_seed_accounts[i as usize].clone());
+        // submit the transaction:
+        let hash = api.tx().sign_and_submit(&tx, &acc_signer, tx_params).await?;
+        println!("Bonding extrinsic submitted for test account {}: {}",i, hash);
+    }
+    // Nominate
+    for i in 0..NB_TEST_ACCOUNTS {
+        if targets[i as usize].is_empty() {
+            continue;
+        }
+        let tx = polkadot::tx().staking().nominate(
+            targets[i as usize].iter().map(|t| t.clone().into()).collect()
+        );
+        let tx_params = Params::new()
+            .tip(PlainTip::new(0))
+            .era(Era::Immortal, api.genesis_hash());
+        let acc_signer = PairSigner::new(acc_seed_accounts[i as usize].clone());
+        // submit the transaction:
+        let hash = api.tx().sign_and_submit(&tx, &acc_signer, tx_params).await?;
+        println!("Nominating extrinsic submitted for test account {}: {}",i, hash);
+    }
+    Ok(targets)
+}
+
+/// Checks the ledger, controller, payee and nominations of every test account bonded by `nominate_all`,
+/// given the validators it nominated.
+pub async fn verify_nominations(api: &OnlineClient<PolkadotConfig>, acc_seed_accounts : &[sr25519::Pair], targets: &[Vec<AccountId32>]) -> Result<(), Box<dyn std::error::Error>> {
+    if targets.iter().all(|t| t.is_empty()) {
+        println!("### The test accounts did not nominate ###");
+        return Ok(());
+    }
+    for i in 0..NB_TEST_ACCOUNTS {
+        let acc_id: AccountId32 = acc_seed_accounts[i as usize].public().into();
+        let controller = api.storage().fetch(&polkadot::storage().staking().bonded(&acc_id), None).await?
+            .unwrap_or_else(|| panic!("ERROR: Test account {} not bonded", i));
+        assert_eq!(controller, acc_id);
+        let ledger = api.storage().fetch(&polkadot::storage().staking().ledger(&controller), None).await?
+            .unwrap_or_else(|| panic!("ERROR: No staking ledger for test account {}", i));
+        assert_eq!(ledger.stash, acc_id);
+        assert_eq!(ledger.total, bond_amount(i));
+        assert_eq!(ledger.active, bond_amount(i));
+        assert!(ledger.unlocking.0.is_empty());
+        match api.storage().fetch_or_default(&polkadot::storage().staking().payee(&acc_id), None).await? {
+            RewardDestination::Account(account) => assert_eq!(account, acc_id),
+            payee => panic!("ERROR: Unexpected reward destination for test account {}: {:?}", i, payee),
+        }
+        let nominations = api.storage().fetch(&polkadot::storage().staking().nominators(&acc_id), None).await?;
+        match nominations {
+            Some(nominations) => {
+                assert_eq!(nominations.targets.0, targets[i as usize]);
+                assert!(!nominations.suppressed);
+            },
+            None => assert!(targets[i as usize].is_empty(), "ERROR: Test account {} is not nominating", i),
+        }
+    }
+    println!("Bonds and nominations of the {} test accounts verified",NB_TEST_ACCOUNTS);
+    Ok(())
+}
+
+/// Checks that the test accounts back the validators they nominated in the exposures of the first era
+/// elected after their nominations, and that the clipped exposures keep the largest backers only.
+pub async fn verify_exposures(api: &OnlineClient<PolkadotConfig>, acc_seed_accounts : &[sr25519::Pair]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut nominations = Vec::new();
+    for i in 0..NB_TEST_ACCOUNTS {
+        let acc_id: AccountId32 = acc_seed_accounts[i as usize].public().into();
+        if let Some(n) = api.storage().fetch(&polkadot::storage().staking().nominators(&acc_id), None).await? {
+            nominations.push((i, acc_id, n));
+        }
+    }
+    if nominations.is_empty() {
+        println!("### The test accounts are not nominating, skipping the exposures ###");
+        return Ok(());
+    }
+    // Nominations are taken into account from the next election on.
+    let submitted_in = nominations.iter().map(|(_, _, n)| n.submitted_in).max().unwrap_or(0);
+    let mut era = api.storage().fetch_or_default(&polkadot::storage().staking().current_era(), None).await?;
+    if era <= submitted_in {
+        let (_, hash) = blocks::wait_for_matching_event::<polkadot::staking::events::StakersElected, _>(api, MAX_ERA_DURATION, |_| true).await?
+            .expect("ERROR: No staking election");
+        era = api.storage().fetch_or_default(&polkadot::storage().staking().current_era(), Some(hash)).await?;
+    }
+    let mut exposures: HashMap<AccountId32, Exposure> = HashMap::new();
+    for (i, acc_id, n) in nominations.iter() {
+        let ledger = api.storage().fetch(&polkadot::storage().staking().ledger(acc_id), None).await?
+            .unwrap_or_else(|| panic!("ERROR: No staking ledger for test account {}", i));
+        let mut elected = 0;
+        let mut backing = 0;
+        for target in n.targets.0.iter() {
+            if !exposures.contains_key(target) {
+                if let Some(exposure) = api.storage().fetch(&polkadot::storage().staking().eras_stakers(&era, target), None).await? {
+                    exposures.insert(target.clone(), exposure);
+                }
+            }
+            if let Some(exposure) = exposures.get(target) {
+                elected += 1;
+                backing += exposure.others.iter().filter(|x| x.who == *acc_id).map(|x| x.value).sum::<u128>();
+            }
+        }
+        // The whole active bond is spread over the elected targets.
+        if elected > 0 {
+            assert_eq!(backing, ledger.active, "ERROR: Test account {} not fully exposed in era {}", i, era);
+        } else {
+            println!("### None of the validators nominated by test account {} was elected in era {} ###",i, era);
+        }
+    }
+    let max_rewarded = api.constants().at(&polkadot::constants().staking().max_nominator_rewarded_per_validator())?;
+    for (validator, exposure) in exposures.iter() {
+        assert_eq!(exposure.total, exposure.own + exposure.others.iter().map(|x| x.value).sum::<u128>());
+        let clipped = api.storage().fetch_or_default(&polkadot::storage().staking().eras_stakers_clipped(&era, validator), None).await?;
+        let mut largest: Vec<u128> = exposure.others.iter().map(|x| x.value).collect();
+        largest.sort_by(|a, b| b.cmp(a));
+        largest.truncate(max_rewarded as usize);
+        assert_eq!(clipped.others.iter().map(|x| x.value).collect::<Vec<u128>>(), largest);
+        assert!(clipped.others.iter().all(|x| exposure.others.iter().any(|y| y.who == x.who && y.value == x.value)));
+        assert_eq!((clipped.total, clipped.own), (exposure.total, exposure.own));
+        println!("Era {} exposure of {}: {} backers, {} rewarded, total {}",era, validator, exposure.others.len(), clipped.others.len(), exposure.total);
+    }
+    Ok(())
+}
+
+/// Amount locked by staking on an account.
+async fn staking_lock(api: &OnlineClient<PolkadotConfig>, acc_id: &AccountId32, at: Option<H256>) -> Result<u128, Box<dyn std::error::Error>> {
+    let locks = api.storage().fetch_or_default(&polkadot::storage().balances().locks(acc_id), at).await?;
+    Ok(locks.0.iter().filter(|l| &l.id == b"staking ").map(|l| l.amount).sum())
+}
+
+/// Unbonds part of the stake of a test account, rebonds some of it and unbonds again, checking
+/// the unlocking chunks up to their maximum, then withdraws the unlocked funds once the bonding
+/// duration is over.
+pub async fn unbonding_lifecycle(api: &OnlineClient<PolkadotConfig>, acc_seed_accounts : &[sr25519::Pair], sudo_seed_account: &sr25519::Pair) -> Result<(), Box<dyn std::error::Error>> {
+    let i = 250;
+    let signer = &acc_seed_accounts[i];
+    let acc_id: AccountId32 = signer.public().into();
+    let ledger = match api.storage().fetch(&polkadot::storage().staking().ledger(&acc_id), None).await? {
+        Some(ledger) => ledger,
+        None => {
+            println!("### Test account {} is not bonded, skipping the unbonding ###",i);
+            return Ok(());
+        },
+    };
+    let (total, active) = (ledger.total, ledger.active);
+    let bonding_duration = api.constants().at(&polkadot::constants().staking().bonding_duration())?;
+    let max_chunks = api.constants().at(&polkadot::constants().staking().max_unlocking_chunks())?;
+    // Unbonding moves funds from the active stake to a chunk unlocked after the bonding duration.
+    let events = submit(api, signer, &polkadot::tx().staking().unbond(active / 4)).await?;
+    let unbonded = events.find_first::<polkadot::staking::events::Unbonded>()?
+        .expect("ERROR: Stake not unbonded");
+    assert_eq!((unbonded.0.clone(), unbonded.1), (acc_id.clone(), active / 4));
+    let era = api.storage().fetch_or_default(&polkadot::storage().staking().current_era(), Some(events.block_hash())).await?;
+    let ledger = api.storage().fetch(&polkadot::storage().staking().ledger(&acc_id), Some(events.block_hash())).await?
+        .expect("ERROR: Staking ledger removed");
+    assert_eq!((ledger.total, ledger.active), (total, active - active / 4));
+    assert_eq!(ledger.unlocking.0.len(), 1);
+    assert_eq!((ledger.unlocking.0[0].value, ledger.unlocking.0[0].era), (active / 4, era + bonding_duration));
+    println!("Test account {} unbonded {}, unlocked at era {}",i, active / 4, era + bonding_duration);
+    // Rebonding takes funds back from the unlocking chunks.
+    let events = submit(api, signer, &polkadot::tx().staking().rebond(active / 8)).await?;
+    let bonded = events.find_first::<polkadot::staking::events::Bonded>()?
+        .expect("ERROR: Stake not rebonded");
+    assert_eq!((bonded.0.clone(), bonded.1), (acc_id.clone(), active / 8));
+    let ledger = api.storage().fetch(&polkadot::storage().staking().ledger(&acc_id), Some(events.block_hash())).await?
+        .expect("ERROR: Staking ledger removed");
+    assert_eq!(ledger.active, active - active / 4 + active / 8);
+    assert_eq!(ledger.unlocking.0.iter().map(|c| c.value).sum::<u128>(), active / 4 - active / 8);
+    // Unbonds within an era share their chunk, which keeps the chunks within MaxUnlockingChunks.
+    for _ in 0..2 {
+        submit(api, signer, &polkadot::tx().staking().unbond(active / 16)).await?;
+    }
+    let ledger = api.storage().fetch(&polkadot::storage().staking().ledger(&acc_id), None).await?
+        .expect("ERROR: Staking ledger removed");
+    let unlocking: u128 = ledger.unlocking.0.iter().map(|c| c.value).sum();
+    assert_eq!(unlocking, active / 4 - active / 8 + 2 * (active / 16));
+    assert_eq!(ledger.active + unlocking, total);
+    assert!(ledger.unlocking.0.len() <= max_chunks as usize);
+    let eras: Vec<u32> = ledger.unlocking.0.iter().map(|c| c.era).collect();
+    let mut distinct = eras.clone();
+    distinct.dedup();
+    assert_eq!(eras, distinct);
+    println!("Test account {} unlocking {} in {} chunks",i, unlocking, eras.len());
+    // Rebonding more than is unlocking only rebonds the unlocking funds.
+    let events = submit(api, signer, &polkadot::tx().staking().rebond(total)).await?;
+    let bonded = events.find_first::<polkadot::staking::events::Bonded>()?
+        .expect("ERROR: Stake not rebonded");
+    assert_eq!(bonded.1, unlocking);
+    let ledger = api.storage().fetch(&polkadot::storage().staking().ledger(&acc_id), Some(events.block_hash())).await?
+        .expect("ERROR: Staking ledger removed");
+    assert_eq!((ledger.active, ledger.total), (total, total));
+    assert!(ledger.unlocking.0.is_empty());
+    errors::assert_module_error(api, submit(api, signer, &polkadot::tx().staking().rebond(active / 8)).await, "NoUnlockChunk");
+    // Funds can only be withdrawn once their chunk is unlocked.
+    let events = submit(api, signer, &polkadot::tx().staking().unbond(active / 2)).await?;
+    let ledger = api.storage().fetch(&polkadot::storage().staking().ledger(&acc_id), Some(events.block_hash())).await?
+        .expect("ERROR: Staking ledger removed");
+    assert_eq!(ledger.unlocking.0.len(), 1);
+    let events = submit(api, signer, &polkadot::tx().staking().withdraw_unbonded(0)).await?;
+    assert!(!events.has::<polkadot::staking::events::Withdrawn>()?);
+    let withdrawn_ledger = api.storage().fetch(&polkadot::storage().staking().ledger(&acc_id), Some(events.block_hash())).await?
+        .expect("ERROR: Staking ledger removed");
+    assert_eq!(withdrawn_ledger.total, total);
+    assert_eq!(staking_lock(api, &acc_id, Some(events.block_hash())).await?, total);
+    // Unbonding in every new era adds a chunk, up to MaxUnlockingChunks. Root forces an era at
+    // every session meanwhile, and a last one to get back to the normal era schedule.
+    sudo(api, sudo_seed_account, Call::Staking(StakingCall::force_new_era_always {})).await?;
+    let step = active / 4 / max_chunks as u128;
+    let mut nb_chunks = 1;
+    let mut era = api.storage().fetch_or_default(&polkadot::storage().staking().current_era(), None).await?;
+    while nb_chunks < max_chunks {
+        let (_, hash) = blocks::wait_for_matching_event::<polkadot::staking::events::StakersElected, _>(api, MAX_ERA_DURATION, |_| true).await?
+            .expect("ERROR: No staking election");
+        era = api.storage().fetch_or_default(&polkadot::storage().staking().current_era(), Some(hash)).await?;
+        let events = submit(api, signer, &polkadot::tx().staking().unbond(step)).await?;
+        let ledger = api.storage().fetch(&polkadot::storage().staking().ledger(&acc_id), Some(events.block_hash())).await?
+            .expect("ERROR: Staking ledger removed");
+        nb_chunks = ledger.unlocking.0.len() as u32;
+    }
+    errors::assert_module_error(api, submit(api, signer, &polkadot::tx().staking().unbond(step)).await, "NoMoreChunks");
+    sudo(api, sudo_seed_account, Call::Staking(StakingCall::force_new_era {})).await?;
+    let ledger = api.storage().fetch(&polkadot::storage().staking().ledger(&acc_id), None).await?
+        .expect("ERROR: Staking ledger removed");
+    let unlocking: u128 = ledger.unlocking.0.iter().map(|c| c.value).sum();
+    assert_eq!(unlocking, active / 2 + (max_chunks - 1) as u128 * step);
+    let unlock_era = ledger.unlocking.0.iter().map(|c| c.era).max().expect("the chunks are full; qed");
+    println!("Test account {} unlocking {} in {} chunks, the maximum",i, unlocking, max_chunks);
+    while era < unlock_era {
+        let (_, hash) = blocks::wait_for_matching_event::<polkadot::staking::events::StakersElected, _>(api, MAX_ERA_DURATION, |_| true).await?
+            .expect("ERROR: No staking election");
+        era = api.storage().fetch_or_default(&polkadot::storage().staking().current_era(), Some(hash)).await?;
+    }
+    let events = submit(api, signer, &polkadot::tx().staking().withdraw_unbonded(0)).await?;
+    let withdrawn = events.find_first::<polkadot::staking::events::Withdrawn>()?
+        .expect("ERROR: Unlocked stake not withdrawn");
+    assert_eq!((withdrawn.0.clone(), withdrawn.1), (acc_id.clone(), unlocking));
+    let ledger = api.storage().fetch(&polkadot::storage().staking().ledger(&acc_id), Some(events.block_hash())).await?
+        .expect("ERROR: Staking ledger removed");
+    assert_eq!((ledger.total, ledger.active), (total - unlocking, total - unlocking));
+    assert!(ledger.unlocking.0.is_empty());
+    // The withdrawn funds are no longer locked.
+    assert_eq!(staking_lock(api, &acc_id, Some(events.block_hash())).await?, ledger.total);
+    let (_, _, misc_frozen, _) = balances::account_data(api, &acc_id, Some(events.block_hash())).await?;
+    assert_eq!(misc_frozen, ledger.total);
+    println!("Test account {} withdrew {} at era {}",i, withdrawn.1, era);
+    Ok(())
+}
+
+/// `Perbill::from_rational`, rounding down.
+fn perbill_from_rational(p: u128, q: u128) -> u32 {
+    if q == 0 || p >= q { 1_000_000_000 } else { (p * 1_000_000_000 / q) as u32 }
+}
+
+/// `parts` billionths of `value`, rounded as the runtime's `Perbill` multiplication does.
+fn perbill_of(parts: u32, value: u128) -> u128 {
+    let parts = parts as u128;
+    value / 1_000_000_000 * parts + (value % 1_000_000_000 * parts + 499_999_999) / 1_000_000_000
+}
+
+/// Has test accounts set every kind of reward destination, pays out the stakers of the validator
+/// they nominate for the last ended era, and checks the rewards against the era points and exposure.
+pub async fn payout_rewards(api: &OnlineClient<PolkadotConfig>, acc_seed_accounts : &[sr25519::Pair], sudo_seed_account: &sr25519::Pair) -> Result<(), Box<dyn std::error::Error>> {
+    let acc_id: AccountId32 = acc_seed_accounts[240].public().into();
+    let targets = match api.storage().fetch(&polkadot::storage().staking().nominators(&acc_id), None).await? {
+        Some(nominations) => nominations.targets.0,
+        None => {
+            println!("### The test accounts are not nominating, skipping the payouts ###");
+            return Ok(());
+        },
+    };
+    // Test accounts 240 to 245 get their rewards in every possible way, the controller and the
+    // beneficiary being new accounts which are not rewarded themselves.
+    let controller = sr25519::Pair::generate().0;
+    let controller_id: AccountId32 = controller.public().into();
+    let beneficiary_id: AccountId32 = sr25519::Pair::generate().0.public().into();
+    let destinations = vec![
+        (240, RewardDestination::Staked),
+        (241, RewardDestination::Stash),
+        (242, RewardDestination::Controller),
+        (243, RewardDestination::Account(beneficiary_id.clone())),
+        (244, RewardDestination::None),
+    ];
+    for (i, destination) in destinations {
+        submit(api, &acc_seed_accounts[i], &polkadot::tx().staking().set_payee(destination)).await?;
+    }
+    submit(api, &acc_seed_accounts[242], &polkadot::tx().staking().set_controller(controller_id.clone().into())).await?;
+    let stash_242: AccountId32 = acc_seed_accounts[242].public().into();
+    assert_eq!(api.storage().fetch(&polkadot::storage().staking().bonded(&stash_242), None).await?, Some(controller_id.clone()));
+    println!("Reward destinations set for test accounts 240 to 244");
+    // Pick the last ended era in which test account 240 was rewarded, and a validator it backed.
+    let mut era_validator = None;
+    for _ in 0..3 {
+        let active_era = api.storage().fetch(&polkadot::storage().staking().active_era(), None).await?.map_or(0, |e| e.index);
+        if active_era > 0 {
+            let reward = api.storage().fetch(&polkadot::storage().staking().eras_validator_reward(&(active_era - 1)), None).await?;
+            for target in targets.iter() {
+                let clipped = api.storage().fetch_or_default(&polkadot::storage().staking().eras_stakers_clipped(&(active_era - 1), target), None).await?;
+                if reward.is_some() && clipped.others.iter().any(|x| x.who == acc_id) {
+                    era_validator = Some((active_era - 1, target.clone()));
+                    break;
+                }
+            }
+            if era_validator.is_some() {
+                break;
+            }
+        }
+        blocks::wait_for_event::<polkadot::staking::events::EraPaid>(api, MAX_ERA_DURATION).await?
+            .expect("ERROR: No era paid");
+    }
+    let (era, validator) = era_validator.expect("ERROR: The test accounts were not rewarded in any recent era");
+    // Expected rewards, from the era points, payout, commission and clipped exposure.
+    let points = api.storage().fetch_or_default(&polkadot::storage().staking().eras_reward_points(&era), None).await?;
+    let validator_points = points.individual.into_iter().find(|(k, _)| k == &validator).map_or(0, |(_, v)| v);
+    let era_payout = api.storage().fetch(&polkadot::storage().staking().eras_validator_reward(&era), None).await?
+        .expect("ERROR: Era reward not recorded");
+    let prefs = api.storage().fetch_or_default(&polkadot::storage().staking().eras_validator_prefs(&era, &validator), None).await?;
+    let exposure = api.storage().fetch_or_default(&polkadot::storage().staking().eras_stakers_clipped(&era, &validator), None).await?;
+    let validator_payout = perbill_of(perbill_from_rational(validator_points as u128, points.total as u128), era_payout);
+    let commission = perbill_of(prefs.commission.0, validator_payout);
+    let leftover = validator_payout - commission;
+    let mut expected: Vec<(AccountId32, u128)> = exposure.others.iter()
+        .map(|x| (x.who.clone(), perbill_of(perbill_from_rational(x.value, exposure.total), leftover)))
+        .collect();
+    expected.push((validator.clone(), commission + perbill_of(perbill_from_rational(exposure.own, exposure.total), leftover)));
+    // The payout is triggered by the sudo account, which does not stake.
+    let signer = sudo_seed_account;
+    let signer_id: AccountId32 = signer.public().into();
+    assert!(expected.iter().all(|(who, _)| *who != signer_id));
+    let events = submit(api, signer, &polkadot::tx().staking().payout_stakers(validator.clone(), era)).await?;
+    let started = events.find_first::<polkadot::staking::events::PayoutStarted>()?
+        .expect("ERROR: Payout not started");
+    assert_eq!((started.0, started.1.clone()), (era, validator.clone()));
+    let mut rewarded = Vec::new();
+    for event in events.find::<polkadot::staking::events::Rewarded>() {
+        let event = event?;
+        rewarded.push((event.0, event.1));
+    }
+    let hash = events.block_hash();
+    let parent = blocks::parent_hash(api, hash).await?;
+    // Rewards below the existential deposit cannot create the new controller and beneficiary.
+    let new_payees: Vec<AccountId32> = vec![acc_seed_accounts[242].public().into(), acc_seed_accounts[243].public().into()];
+    for (who, reward) in expected.iter() {
+        let payee = api.storage().fetch_or_default(&polkadot::storage().staking().payee(who), Some(parent)).await?;
+        let event = rewarded.iter().find(|(w, _)| w == who);
+        match payee {
+            RewardDestination::None => assert!(event.is_none()),
+            _ if *reward == 0 => {},
+            _ if new_payees.contains(who) && *reward < EXISTENTIAL_DEPOSIT => assert_eq!(event.map(|(_, r)| *r), Some(0)),
+            _ => assert_eq!(event.map(|(_, r)| *r), Some(*reward), "ERROR: Unexpected reward of {} in era {}", who, era),
+        }
+    }
+    println!("Era {} paid out to {} stakers of {}",era, rewarded.len(), validator);
+    // Each reward lands where its destination says.
+    let reward_of = |i: usize| {
+        let who: AccountId32 = acc_seed_accounts[i].public().into();
+        expected.iter().find(|(w, _)| *w == who).map_or(0, |(_, r)| *r)
+    };
+    let stash = |i: usize| -> AccountId32 { acc_seed_accounts[i].public().into() };
+    let ledger_before = api.storage().fetch(&polkadot::storage().staking().ledger(&stash(240)), Some(parent)).await?
+        .expect("ERROR: Staking ledger removed");
+    let ledger_after = api.storage().fetch(&polkadot::storage().staking().ledger(&stash(240)), Some(hash)).await?
+        .expect("ERROR: Staking ledger removed");
+    assert_eq!(ledger_after.active - ledger_before.active, reward_of(240));
+    assert_eq!(ledger_after.total - ledger_before.total, reward_of(240));
+    assert_eq!(balances::balance_deltas(api, &stash(240), hash).await?.0, reward_of(240) as i128);
+    assert_eq!(balances::balance_deltas(api, &stash(241), hash).await?.0, reward_of(241) as i128);
+    assert_eq!(balances::balance_deltas(api, &stash_242, hash).await?.0, 0);
+    if reward_of(242) >= EXISTENTIAL_DEPOSIT {
+        assert_eq!(balances::balance_deltas(api, &controller_id, hash).await?.0, reward_of(242) as i128);
+    }
+    if reward_of(243) >= EXISTENTIAL_DEPOSIT {
+        assert_eq!(balances::balance_deltas(api, &beneficiary_id, hash).await?.0, reward_of(243) as i128);
+    }
+    assert_eq!(balances::balance_deltas(api, &stash(244), hash).await?.0, 0);
+    assert_eq!(balances::balance_deltas(api, &stash(245), hash).await?.0, reward_of(245) as i128);
+    // Rewards can only be claimed once.
+    let validator_controller = api.storage().fetch(&polkadot::storage().staking().bonded(&validator), None).await?
+        .expect("ERROR: Validator not bonded");
+    let ledger = api.storage().fetch(&polkadot::storage().staking().ledger(&validator_controller), None).await?
+        .expect("ERROR: No staking ledger for the validator");
+    assert!(ledger.claimed_rewards.contains(&era));
+    let result = submit(api, signer, &polkadot::tx().staking().payout_stakers(validator.clone(), era)).await;
+    errors::assert_module_error(api, result, "AlreadyClaimed");
+    println!("Rewards of era {} credited to every reward destination",era);
+    // Test account 242 controls its stake again, as the later checks expect.
+    let events = submit(api, &acc_seed_accounts[242], &polkadot::tx().staking().set_controller(stash_242.clone().into())).await?;
+    assert_eq!(api.storage().fetch(&polkadot::storage().staking().bonded(&stash_242), Some(events.block_hash())).await?, Some(stash_242.clone()));
+    Ok(())
+}
+
+/// Checks that nominations of blocked validators and of too many validators are rejected,
+/// while nominating a chilled validator is accepted.
+pub async fn nomination_rejections(api: &OnlineClient<PolkadotConfig>, acc_seed_accounts : &[sr25519::Pair]) -> Result<(), Box<dyn std::error::Error>> {
+    let (validator, nominator) = (&acc_seed_accounts[251], &acc_seed_accounts[252]);
+    let validator_id: AccountId32 = validator.public().into();
+    let nominator_id: AccountId32 = nominator.public().into();
+    let validator_nominations = api.storage().fetch(&polkadot::storage().staking().nominators(&validator_id), None).await?;
+    let nominator_nominations = api.storage().fetch(&polkadot::storage().staking().nominators(&nominator_id), None).await?;
+    let (validator_targets, nominator_targets) = match (validator_nominations, nominator_nominations) {
+        (Some(v), Some(n)) => (v.targets.0, n.targets.0),
+        _ => {
+            println!("### Test accounts 251 and 252 are not nominating, skipping the rejections ###");
+            return Ok(());
+        },
+    };
+    // A validator blocking nominations cannot be nominated.
+    let prefs = ValidatorPrefs { commission: Perbill(0), blocked: true };
+    submit(api, validator, &polkadot::tx().staking().validate(prefs)).await?;
+    let prefs = api.storage().fetch_or_default(&polkadot::storage().staking().validators(&validator_id), None).await?;
+    assert!(prefs.blocked);
+    let tx = polkadot::tx().staking().nominate(vec![validator_id.clone().into()]);
+    errors::assert_module_error(api, submit(api, nominator, &tx).await, "BadTarget");
+    println!("Nomination of blocked validator {} rejected",validator_id);
+    // No more than MaxNominations validators can be nominated.
+    let max_nominations = api.constants().at(&polkadot::constants().staking().max_nominations())?;
+    let targets = acc_seed_accounts[..max_nominations as usize + 1].iter().map(|x| AccountId32::from(x.public()).into()).collect();
+    let tx = polkadot::tx().staking().nominate(targets);
+    errors::assert_module_error(api, submit(api, nominator, &tx).await, "TooManyTargets");
+    println!("Nomination of {} validators rejected",max_nominations + 1);
+    // A chilled validator can still be nominated.
+    let events = submit(api, validator, &polkadot::tx().staking().chill()).await?;
+    let chilled = events.find_first::<polkadot::staking::events::Chilled>()?
+        .expect("ERROR: Validator not chilled");
+    assert_eq!(chilled.0, validator_id);
+    let prefs = api.storage().fetch(&polkadot::storage().staking().validators(&validator_id), Some(events.block_hash())).await?;
+    assert!(prefs.is_none());
+    let tx = polkadot::tx().staking().nominate(vec![validator_id.clone().into()]);
+    submit(api, nominator, &tx).await?;
+    let nominations = api.storage().fetch(&polkadot::storage().staking().nominators(&nominator_id), None).await?
+        .expect("ERROR: Nomination of a chilled validator not registered");
+    assert_eq!(nominations.targets.0, vec![validator_id.clone()]);
+    println!("Nomination of chilled validator {} accepted",validator_id);
+    // Both accounts go back to their former nominations.
+    let tx = polkadot::tx().staking().nominate(nominator_targets.into_iter().map(|t| t.into()).collect());
+    submit(api, nominator, &tx).await?;
+    let tx = polkadot::tx().staking().nominate(validator_targets.into_iter().map(|t| t.into()).collect());
+    submit(api, validator, &tx).await?;
+    Ok(())
+}
+
+/// Random session public keys, whose private keys are held by no node.
+fn random_session_keys() -> SessionKeys {
+    let raw: Vec<u8> = (0..128).map(|_| rand::random::<u8>()).collect();
+    SessionKeys::decode(&mut &raw[..]).expect("any 128 bytes are valid public keys; qed")
+}
+
+/// Session keys generated in the keystore of the node, or random public keys if the node does not
+/// expose `author_rotateKeys`. Only the former can be used to author blocks and vote on finality.
+async fn session_keys(api: &OnlineClient<PolkadotConfig>) -> Result<(SessionKeys, bool), Box<dyn std::error::Error>> {
+    match api.rpc().rotate_keys().await {
+        Ok(keys) => Ok((SessionKeys::decode(&mut &keys.0[..]).expect("the node generates the runtime session keys; qed"), true)),
+        Err(e) => {
+            println!("### Session keys not rotated by the node, using random keys: {:?} ###",e);
+            Ok((random_session_keys(), false))
+        },
+    }
+}
+
+/// Turns test accounts into validators with their own session keys and commissions, chills them,
+/// has one chilled by another account, and has one enter the active set.
+pub async fn validator_lifecycle(api: &OnlineClient<PolkadotConfig>, acc_seed_accounts : &[sr25519::Pair], sudo_seed_account: &sr25519::Pair) -> Result<(), Box<dyn std::error::Error>> {
+    let validators = &acc_seed_accounts[253..256];
+    let validator_ids: Vec<AccountId32> = validators.iter().map(|v| v.public().into()).collect();
+    for acc_id in validator_ids.iter() {
+        if api.storage().fetch(&polkadot::storage().staking().ledger(acc_id), None).await?.is_none() {
+            println!("### Test account {} is not bonded, skipping the validators ###",acc_id);
+            return Ok(());
+        }
+    }
+    // Every validator registers its session keys, which are unique.
+    let mut rotated = true;
+    let mut keys = Vec::new();
+    for (v, acc_id) in validators.iter().zip(validator_ids.iter()) {
+        let (session_keys, from_node) = session_keys(api).await?;
+        rotated &= from_node;
+        let encoded = session_keys.encode();
+        let events = submit(api, v, &polkadot::tx().session().set_keys(session_keys, vec![])).await?;
+        let next_keys = api.storage().fetch(&polkadot::storage().session().next_keys(acc_id), Some(events.block_hash())).await?
+            .expect("ERROR: Session keys not registered");
+        assert_eq!(next_keys.encode(), encoded);
+        keys.push(encoded);
+        println!("Session keys of {} registered",acc_id);
+    }
+    let duplicate = SessionKeys::decode(&mut &keys[0][..]).expect("encoded from session keys; qed");
+    let tx = polkadot::tx().session().set_keys(duplicate, vec![]);
+    errors::assert_module_error(api, submit(api, &acc_seed_accounts[256], &tx).await, "DuplicatedKey");
+    // They validate with various commissions, which stops their nominations.
+    let min_commission = api.storage().fetch_or_default(&polkadot::storage().staking().min_commission(), None).await?;
+    let commissions: Vec<u32> = [0, 50_000_000, 1_000_000_000].iter().map(|c| max(*c, min_commission.0)).collect();
+    for ((v, acc_id), commission) in validators.iter().zip(validator_ids.iter()).zip(commissions.iter()) {
+        let prefs = ValidatorPrefs { commission: Perbill(*commission), blocked: false };
+        let events = submit(api, v, &polkadot::tx().staking().validate(prefs)).await?;
+        let prefs = api.storage().fetch(&polkadot::storage().staking().validators(acc_id), Some(events.block_hash())).await?
+            .expect("ERROR: Validator not registered");
+        assert_eq!((prefs.commission.0, prefs.blocked), (*commission, false));
+        let nominations = api.storage().fetch(&polkadot::storage().staking().nominators(acc_id), Some(events.block_hash())).await?;
+        assert!(nominations.is_none());
+        println!("Validator {} registered with commission {}",acc_id, commission);
+    }
+    if min_commission.0 > 0 {
+        let prefs = ValidatorPrefs { commission: Perbill(min_commission.0 - 1), blocked: false };
+        errors::assert_module_error(api, submit(api, &validators[0], &polkadot::tx().staking().validate(prefs)).await, "CommissionTooLow");
+    }
+    let candidates = validator_candidates(api).await?;
+    assert!(validator_ids.iter().all(|v| candidates.contains(v)));
+    // A validator can chill itself.
+    let events = submit(api, &validators[2], &polkadot::tx().staking().chill()).await?;
+    let chilled = events.find_first::<polkadot::staking::events::Chilled>()?
+        .expect("ERROR: Validator not chilled");
+    assert_eq!(chilled.0, validator_ids[2]);
+    assert!(!validator_candidates(api).await?.contains(&validator_ids[2]));
+    // Other accounts can only chill it under the limits set by root.
+    let tx = polkadot::tx().staking().chill_other(validator_ids[1].clone());
+    errors::assert_module_error(api, submit(api, &acc_seed_accounts[2], &tx).await, "CannotChillOther");
+    let min_validator_bond = api.storage().fetch_or_default(&polkadot::storage().staking().min_validator_bond(), None).await?;
+    let max_validator_count = api.storage().fetch(&polkadot::storage().staking().max_validators_count(), None).await?;
+    let chill_threshold = api.storage().fetch(&polkadot::storage().staking().chill_threshold(), None).await?;
+    let nb_validators = api.storage().fetch_or_default(&polkadot::storage().staking().counter_for_validators(), None).await?;
+    let call = Call::Staking(StakingCall::set_staking_configs {
+        min_nominator_bond: ConfigOp::Noop,
+        min_validator_bond: ConfigOp::Set(TEST_ACCOUNT_FUNDING),
+        max_nominator_count: ConfigOp::Noop,
+        max_validator_count: ConfigOp::Set(nb_validators),
+        chill_threshold: ConfigOp::Set(Percent(0)),
+        min_commission: ConfigOp::Noop,
+    });
+    sudo(api, sudo_seed_account, call).await?;
+    let events = submit(api, &acc_seed_accounts[2], &tx).await?;
+    let chilled = events.find_first::<polkadot::staking::events::Chilled>()?
+        .expect("ERROR: Validator not chilled by another account");
+    assert_eq!(chilled.0, validator_ids[1]);
+    let call = Call::Staking(StakingCall::set_staking_configs {
+        min_nominator_bond: ConfigOp::Noop,
+        min_validator_bond: ConfigOp::Set(min_validator_bond),
+        max_nominator_count: ConfigOp::Noop,
+        max_validator_count: max_validator_count.map_or(ConfigOp::Remove, ConfigOp::Set),
+        chill_threshold: chill_threshold.map_or(ConfigOp::Remove, ConfigOp::Set),
+        min_commission: ConfigOp::Noop,
+    });
+    sudo(api, sudo_seed_account, call).await?;
+    println!("Validators {} and {} chilled",validator_ids[2], validator_ids[1]);
+    // The remaining validator enters the active set, unless that could stall finality:
+    // its keys share the node of an existing validator, which votes with a single key.
+    let active = api.storage().fetch_or_default(&polkadot::storage().session().validators(), None).await?;
+    if !rotated || active.len() < 3 {
+        println!("### Not enough validators to add {} to the active set safely ###",validator_ids[0]);
+        submit(api, &validators[0], &polkadot::tx().staking().chill()).await?;
+        return Ok(());
+    }
+    let validator_count = api.storage().fetch_or_default(&polkadot::storage().staking().validator_count(), None).await?;
+    sudo(api, sudo_seed_account, Call::Staking(StakingCall::set_validator_count { new: active.len() as u32 + 1 })).await?;
+    let (_, hash) = blocks::wait_for_matching_event::<polkadot::staking::events::StakersElected, _>(api, MAX_ERA_DURATION, |_| true).await?
+        .expect("ERROR: No staking election");
+    let era = api.storage().fetch_or_default(&polkadot::storage().staking().current_era(), Some(hash)).await?;
+    let exposure = api.storage().fetch(&polkadot::storage().staking().eras_stakers(&era, &validator_ids[0]), Some(hash)).await?
+        .expect("ERROR: Validator not elected");
+    let prefs = api.storage().fetch_or_default(&polkadot::storage().staking().eras_validator_prefs(&era, &validator_ids[0]), Some(hash)).await?;
+    assert_eq!(prefs.commission.0, commissions[0]);
+    println!("Validator {} elected for era {} with {} backing",validator_ids[0], era, exposure.total);
+    let sessions_per_era = api.constants().at(&polkadot::constants().staking().sessions_per_era())?;
+    let mut active = false;
+    for _ in 0..sessions_per_era {
+        let (_, hash) = blocks::wait_for_matching_event::<polkadot::session::events::NewSession, _>(api, MAX_ERA_DURATION, |_| true).await?
+            .expect("ERROR: No new session");
+        let validators = api.storage().fetch_or_default(&polkadot::storage().session().validators(), Some(hash)).await?;
+        if validators.contains(&validator_ids[0]) {
+            active = true;
+            break;
+        }
+    }
+    assert!(active, "ERROR: Validator {} elected but not active", validator_ids[0]);
+    println!("Validator {} entered the active set",validator_ids[0]);
+    // Leave the active set as it was.
+    submit(api, &validators[0], &polkadot::tx().staking().chill()).await?;
+    sudo(api, sudo_seed_account, Call::Staking(StakingCall::set_validator_count { new: validator_count })).await?;
+    Ok(())
+}
+
+/// Amount already slashed from `stash` in the slashing span containing `era`, in the block with the
+/// given hash, or `None` if the era is before the spans kept for the stash.
+async fn prior_span_slash(api: &OnlineClient<PolkadotConfig>, stash: &AccountId32, era: u32, hash: H256) -> Result<Option<u128>, Box<dyn std::error::Error>> {
+    let spans = match api.storage().fetch(&polkadot::storage().staking().slashing_spans(stash), Some(hash)).await? {
+        Some(spans) => spans,
+        None => return Ok(Some(0)),
+    };
+    // The current span starts at `last_start`, `prior` holds the lengths of the previous ones.
+    let (mut index, mut start) = (spans.span_index, spans.last_start);
+    let mut prior = spans.prior.iter();
+    while era < start {
+        match prior.next() {
+            Some(length) => {
+                index -= 1;
+                start = start.saturating_sub(*length);
+            },
+            None => return Ok(None),
+        }
+    }
+    let record = api.storage().fetch_or_default(&polkadot::storage().staking().span_slash(&(stash.clone(), index)), Some(hash)).await?;
+    Ok(Some(record.slashed))
+}
+
+/// Checks a deferred slash against the slash fraction of its validator in the offence era:
+/// the validator and each of its nominators lose the same fraction of their exposure, minus what
+/// was already slashed from them in the same slashing span.
+async fn check_unapplied_slash(api: &OnlineClient<PolkadotConfig>, test_accounts: &HashMap<AccountId32, usize>, slash: &UnappliedSlash, eras: &[u32], hash: H256) -> Result<(), Box<dyn std::error::Error>> {
+    let mut offence = None;
+    for era in eras.iter() {
+        if let Some(s) = api.storage().fetch(&polkadot::storage().staking().validator_slash_in_era(era, &slash.validator), Some(hash)).await? {
+            offence = Some((*era, s));
+            break;
+        }
+    }
+    let (era, (fraction, own_slash)) = offence.expect("ERROR: Deferred slash without validator slash");
+    let exposure = api.storage().fetch_or_default(&polkadot::storage().staking().eras_stakers(&era, &slash.validator), Some(hash)).await?;
+    assert_eq!(own_slash, perbill_of(fraction.0, exposure.own));
+    // Earlier slashes in the same span are deducted.
+    let parent = blocks::parent_hash(api, hash).await?;
+    let expected = |slash: u128, prior: Option<u128>| prior.map_or(0, |p| slash.saturating_sub(p));
+    assert_eq!(slash.own, expected(own_slash, prior_span_slash(api, &slash.validator, era, parent).await?));
+    for (nominator, amount) in slash.others.iter() {
+        let value = exposure.others.iter().find(|x| &x.who == nominator)
+            .expect("ERROR: Slashed nominator not exposed").value;
+        let nominator_slash = perbill_of(fraction.0, value);
+        assert_eq!(*amount, expected(nominator_slash, prior_span_slash(api, nominator, era, parent).await?));
+        if let Some(i) = test_accounts.get(nominator) {
+            println!("Test account {} slashed {} of {} backing {} in era {}",i, amount, value, slash.validator, era);
+        }
+    }
+    println!("Deferred slash of {} for era {}: {} billionths, own {}, {} nominators",slash.validator, era, fraction.0, slash.own, slash.others.len());
+    Ok(())
+}
+
+/// Forces a new era electing test validators whose session keys are held by no node, so that they
+/// are reported for missing their heartbeats, then checks the deferred slashes against the
+/// exposures, cancels one of them and checks the others are applied.
+pub async fn slashing_scenario(api: &OnlineClient<PolkadotConfig>, acc_seed_accounts : &[sr25519::Pair], sudo_seed_account: &sr25519::Pair) -> Result<(), Box<dyn std::error::Error>> {
+    let test_accounts: HashMap<AccountId32, usize> = acc_seed_accounts.iter().enumerate()
+        .map(|(i, acc)| (acc.public().into(), i))
+        .collect();
+    let defer_duration = api.constants().at(&polkadot::constants().staking().slash_defer_duration())?;
+    // Unresponsive validators are only slashed when more than a tenth of the set plus one of them
+    // are offline, and fewer than a third of the set can be offline for finality to proceed.
+    let active = api.storage().fetch_or_default(&polkadot::storage().session().validators(), None).await?;
+    let candidates = &acc_seed_accounts[257..260];
+    let nb_offline = (2..=candidates.len())
+        .find(|&k| k > (active.len() + k) / 10 + 1 && 3 * k < active.len() + k)
+        .unwrap_or_else(|| panic!("ERROR: Cannot take validators offline safely with {} active validators", active.len()));
+    let offline = &candidates[..nb_offline];
+    let offline_ids: Vec<AccountId32> = offline.iter().map(|v| v.public().into()).collect();
+    let min_commission = api.storage().fetch_or_default(&polkadot::storage().staking().min_commission(), None).await?;
+    for (v, acc_id) in offline.iter().zip(offline_ids.iter()) {
+        assert!(api.storage().fetch(&polkadot::storage().staking().ledger(acc_id), None).await?.is_some(), "ERROR: Test account {} is not bonded", acc_id);
+        submit(api, v, &polkadot::tx().session().set_keys(random_session_keys(), vec![])).await?;
+        submit(api, v, &polkadot::tx().staking().validate(ValidatorPrefs { commission: Perbill(min_commission.0), blocked: false })).await?;
+    }
+    let validator_count = api.storage().fetch_or_default(&polkadot::storage().staking().validator_count(), None).await?;
+    sudo(api, sudo_seed_account, Call::Staking(StakingCall::set_validator_count { new: (active.len() + nb_offline) as u32 })).await?;
+    println!("{} validators without node registered",nb_offline);
+    // A forced era is planned at the next session, after which forcing stops.
+    let events = sudo(api, sudo_seed_account, Call::Staking(StakingCall::force_new_era {})).await?;
+    let forcing = api.storage().fetch_or_default(&polkadot::storage().staking().force_era(), Some(events.block_hash())).await?;
+    assert!(matches!(forcing, Forcing::ForceNew));
+    let era = api.storage().fetch_or_default(&polkadot::storage().staking().current_era(), Some(events.block_hash())).await?;
+    let (_, hash) = blocks::wait_for_matching_event::<polkadot::staking::events::StakersElected, _>(api, MAX_ERA_DURATION, |_| true).await?
+        .expect("ERROR: No staking election after forcing a new era");
+    let forced_era = api.storage().fetch_or_default(&polkadot::storage().staking().current_era(), Some(hash)).await?;
+    let forcing = api.storage().fetch_or_default(&polkadot::storage().staking().force_era(), Some(hash)).await?;
+    assert_eq!(forced_era, era + 1);
+    assert!(matches!(forcing, Forcing::NotForcing));
+    for acc_id in offline_ids.iter() {
+        assert!(api.storage().fetch(&polkadot::storage().staking().eras_stakers(&forced_era, acc_id), Some(hash)).await?.is_some(), "ERROR: Validator {} not elected", acc_id);
+    }
+    println!("Era {} forced",forced_era);
+    // The validators without node miss their heartbeats in their first session.
+    let (offence, hash) = blocks::wait_for_matching_event::<polkadot::offences::events::Offence, _>(api, MAX_ERA_DURATION, |_| true).await?
+        .expect("ERROR: No offence reported for the validators without node");
+    // They leave the active set at the next era.
+    for v in offline.iter() {
+        submit(api, v, &polkadot::tx().staking().chill()).await?;
+    }
+    sudo(api, sudo_seed_account, Call::Staking(StakingCall::set_validator_count { new: validator_count })).await?;
+    // Deferred slashes are stored under the era the offence is reported in, and applied at the
+    // start of the era after the defer duration.
+    let report_era = api.storage().fetch(&polkadot::storage().staking().active_era(), Some(hash)).await?
+        .expect("ERROR: No active era").index;
+    let apply_era = report_era + defer_duration + 1;
+    println!("Offence {:?} reported in era {}",String::from_utf8_lossy(&offence.kind), report_era);
+    let slashes = api.storage().fetch_or_default(&polkadot::storage().staking().unapplied_slashes(&report_era), Some(hash)).await?;
+    assert!(!slashes.is_empty(), "ERROR: Offence reported without deferred slashes");
+    let eras = [report_era, report_era.saturating_sub(1)];
+    for slash in slashes.iter() {
+        check_unapplied_slash(api, &test_accounts, slash, &eras, hash).await?;
+    }
+    // Root can cancel a deferred slash before it is applied.
+    let mut slashes = slashes;
+    if slashes.len() > 1 {
+        let index = slashes.len() as u32 - 1;
+        let events = sudo(api, sudo_seed_account, Call::Staking(StakingCall::cancel_deferred_slash { era: report_era, slash_indices: vec![index] })).await?;
+        let remaining = api.storage().fetch_or_default(&polkadot::storage().staking().unapplied_slashes(&report_era), Some(events.block_hash())).await?;
+        let canceled = slashes.pop().expect("there are several slashes; qed");
+        assert_eq!(remaining.encode(), slashes.encode());
+        println!("Deferred slash of {} canceled",canceled.validator);
+    }
+    // The other slashes are applied when their era starts.
+    let events = blocks::wait_for_event::<polkadot::staking::events::Slashed>(api, MAX_ERA_DURATION * (defer_duration + 2)).await?
+        .expect("ERROR: Deferred slashes not applied");
+    let hash = events.block_hash();
+    let active_era = api.storage().fetch(&polkadot::storage().staking().active_era(), Some(hash)).await?
+        .expect("ERROR: No active era").index;
+    assert_eq!(active_era, apply_era);
+    assert!(api.storage().fetch_or_default(&polkadot::storage().staking().unapplied_slashes(&report_era), Some(hash)).await?.is_empty());
+    let mut slashed = Vec::new();
+    for event in events.find::<polkadot::staking::events::Slashed>() {
+        let event = event?;
+        slashed.push((event.0, event.1));
+    }
+    let mut expected: HashMap<AccountId32, u128> = HashMap::new();
+    for slash in slashes.iter() {
+        for (who, amount) in std::iter::once((&slash.validator, &slash.own)).chain(slash.others.iter().map(|(w, a)| (w, a))) {
+            if *amount == 0 {
+                continue;
+            }
+            let found = slashed.iter().position(|s| s == &(who.clone(), *amount))
+                .unwrap_or_else(|| panic!("ERROR: Slash of {} by {} not applied", who, amount));
+            slashed.remove(found);
+            *expected.entry(who.clone()).or_insert(0) += *amount;
+        }
+    }
+    // The slashed amounts are taken from the active stake of the test accounts.
+    let parent = blocks::parent_hash(api, hash).await?;
+    for (who, amount) in expected.iter() {
+        let i = match test_accounts.get(who) {
+            Some(i) => i,
+            None => continue,
+        };
+        let controller = api.storage().fetch(&polkadot::storage().staking().bonded(who), Some(hash)).await?
+            .expect("ERROR: Slashed stash not bonded");
+        let before = api.storage().fetch(&polkadot::storage().staking().ledger(&controller), Some(parent)).await?
+            .expect("ERROR: No staking ledger");
+        let after = api.storage().fetch(&polkadot::storage().staking().ledger(&controller), Some(hash)).await?
+            .expect("ERROR: Staking ledger removed");
+        assert_eq!(before.total - after.total, *amount);
+        assert_eq!(balances::balance_deltas(api, who, hash).await?.0, -(*amount as i128));
+        println!("Test account {} slashed {} in era {}",i, amount, apply_era);
+    }
+    println!("{} deferred slashes applied in era {}",slashes.len(), apply_era);
+    Ok(())
+}
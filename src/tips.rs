@@ -0,0 +1,197 @@
+use subxt::{
+    ext::{
+        sp_core::{sr25519, Pair, H256},
+        sp_runtime::{
+            AccountId32,
+            traits::{BlakeTwo256, Hash}
+        },
+    },
+    OnlineClient,
+    PolkadotConfig,
+};
+use crate::consts::*;
+use crate::{balances, blocks, errors, treasury};
+use crate::tx::{submit, sudo};
+
+#[subxt::subxt(runtime_metadata_path = "./data/metadata.scale")]
+pub mod polkadot {}
+
+type Call = polkadot::runtime_types::edgeware_runtime::Call;
+type TipsCall = polkadot::runtime_types::pallet_tips::pallet::Call;
+
+/// Hash identifying the tip of `who` for `reason`.
+pub fn tip_hash(reason: &[u8], who: &AccountId32) -> H256 {
+    BlakeTwo256::hash_of(&(BlakeTwo256::hash(reason), who))
+}
+
+/// Deposit reserved from the reporter of a tip with the given reason.
+pub fn report_deposit(api: &OnlineClient<PolkadotConfig>, reason: &[u8]) -> Result<u128, Box<dyn std::error::Error>> {
+    let base = api.constants().at(&polkadot::constants().tips().tip_report_deposit_base())?;
+    let per_byte = api.constants().at(&polkadot::constants().tips().data_deposit_per_byte())?;
+    Ok(base + per_byte * reason.len() as u128)
+}
+
+/// Reports a tip for `who` and checks the deposit reserved from the finder.
+async fn report(api: &OnlineClient<PolkadotConfig>, finder: &sr25519::Pair, reason: &[u8], who: &AccountId32) -> Result<H256, Box<dyn std::error::Error>> {
+    let finder_id: AccountId32 = finder.public().into();
+    let tx = polkadot::tx().tips().report_awesome(reason.to_vec(), who.clone());
+    let events = submit(api, finder, &tx).await?;
+    let new_tip = events.find_first::<polkadot::tips::events::NewTip>()?
+        .expect("ERROR: Tip incorrectly registered");
+    assert_eq!(new_tip.tip_hash, tip_hash(reason, who));
+    let deposit = report_deposit(api, reason)?;
+    let (_, reserved) = balances::balance_deltas(api, &finder_id, events.block_hash()).await?;
+    assert_eq!(reserved, deposit as i128);
+    let tip = api.storage().fetch(&polkadot::storage().tips().tips(&new_tip.tip_hash), Some(events.block_hash())).await?
+        .expect("ERROR: Tip incorrectly registered");
+    assert_eq!(tip.finder, finder_id);
+    assert_eq!(tip.deposit, deposit);
+    assert!(tip.finders_fee);
+    assert!(tip.closes.is_none());
+    let stored = api.storage().fetch(&polkadot::storage().tips().reasons(&tip.reason), Some(events.block_hash())).await?
+        .expect("ERROR: Tip reason incorrectly registered");
+    assert_eq!(stored, reason);
+    println!("Tip reported for {}, deposit {}: {:?}",who, deposit, new_tip.tip_hash);
+    Ok(new_tip.tip_hash)
+}
+
+/// Has the given councillors tip `hash`, checking that the tip starts closing once half the
+/// tippers have tipped. Returns the block at which it can be closed.
+async fn tip_all(api: &OnlineClient<PolkadotConfig>, tippers: &[(sr25519::Pair, u128)], hash: H256, nb_members: usize) -> Result<u32, Box<dyn std::error::Error>> {
+    let countdown = api.constants().at(&polkadot::constants().tips().tip_countdown())?;
+    let threshold = (nb_members + 1) / 2;
+    let mut closes = None;
+    for (k, (tipper, value)) in tippers.iter().enumerate() {
+        let events = submit(api, tipper, &polkadot::tx().tips().tip(hash, *value)).await?;
+        let closing = events.find_first::<polkadot::tips::events::TipClosing>()?;
+        let tip = api.storage().fetch(&polkadot::storage().tips().tips(&hash), Some(events.block_hash())).await?
+            .expect("ERROR: Tip incorrectly registered");
+        let tipper_id: AccountId32 = tipper.public().into();
+        assert!(tip.tips.iter().any(|(t, v)| *t == tipper_id && v == value));
+        if tip.tips.len() == threshold {
+            assert_eq!(closing.map(|c| c.tip_hash), Some(hash));
+            let number = blocks::block_number(api, Some(events.block_hash())).await?;
+            assert_eq!(tip.closes, Some(number + countdown));
+            println!("Tip {:?} closing at block {}",hash, number + countdown);
+        } else {
+            assert!(closing.is_none());
+        }
+        closes = tip.closes;
+        println!("Councillor {} tipped {} on {:?}",k, value, hash);
+    }
+    Ok(closes.expect("ERROR: Tip not closing after the tippers threshold"))
+}
+
+/// Closes `hash` from `closer`, checking that `who` is paid the median tip, less the finder's fee
+/// which goes to the finder along with their deposit.
+async fn close(api: &OnlineClient<PolkadotConfig>, closer: &sr25519::Pair, hash: H256, tip_values: &[u128]) -> Result<(), Box<dyn std::error::Error>> {
+    let tip = api.storage().fetch(&polkadot::storage().tips().tips(&hash), None).await?
+        .expect("ERROR: Tip incorrectly registered");
+    let finders_fee = api.constants().at(&polkadot::constants().tips().tip_finders_fee())?;
+    let mut sorted = tip_values.to_vec();
+    sorted.sort();
+    let median = sorted[sorted.len() / 2].min(treasury::pot(api).await?);
+    let fee = if tip.finders_fee && tip.finder != tip.who { median * finders_fee.0 as u128 / 100 } else { 0 };
+    let events = submit(api, closer, &polkadot::tx().tips().close_tip(hash)).await?;
+    let closed = events.find_first::<polkadot::tips::events::TipClosed>()?
+        .expect("ERROR: Tip not closed");
+    assert_eq!(closed.tip_hash, hash);
+    assert_eq!(closed.who, tip.who);
+    assert_eq!(closed.payout, median - fee);
+    let (free, _) = balances::balance_deltas(api, &tip.who, events.block_hash()).await?;
+    assert_eq!(free, (median - fee) as i128);
+    let (free, reserved) = balances::balance_deltas(api, &tip.finder, events.block_hash()).await?;
+    assert_eq!(free, (fee + tip.deposit) as i128);
+    assert_eq!(reserved, -(tip.deposit as i128));
+    let remaining = api.storage().fetch(&polkadot::storage().tips().tips(&hash), Some(events.block_hash())).await?;
+    assert!(remaining.is_none());
+    let reason = api.storage().fetch(&polkadot::storage().tips().reasons(&tip.reason), Some(events.block_hash())).await?;
+    assert!(reason.is_none());
+    println!("Tip {:?} closed: {} paid to {}, finder's fee {}",hash, closed.payout, closed.who, fee);
+    Ok(())
+}
+
+/// Runs tips reported by a finder and opened by a councillor through the council's tipping and
+/// closing, then retracts and slashes reported tips.
+pub async fn tip_lifecycle(api: &OnlineClient<PolkadotConfig>, acc_seed_accounts : &[sr25519::Pair], sudo_seed_account: &sr25519::Pair) -> Result<(), Box<dyn std::error::Error>> {
+    let members = api.storage().fetch_or_default(&polkadot::storage().council().members(), None).await?;
+    let councillors: Vec<sr25519::Pair> = members.iter()
+        .filter_map(|m| acc_seed_accounts.iter().find(|x| *m == x.public().into()).cloned())
+        .collect();
+    if councillors.len() != members.len() || members.len() < 2 {
+        println!("### The council is not made of at least 2 test accounts, skipping the tips ###");
+        return Ok(());
+    }
+    treasury::fund(api, sudo_seed_account, TEST_ACCOUNT_FUNDING).await?;
+    let (finder, closer) = (&acc_seed_accounts[18], &acc_seed_accounts[20]);
+    let finder_id: AccountId32 = finder.public().into();
+    let who: AccountId32 = acc_seed_accounts[19].public().into();
+    let other_who: AccountId32 = acc_seed_accounts[21].public().into();
+    // Distinct tips from every councillor, so that the median is well defined.
+    let tip_values: Vec<u128> = (0..members.len()).map(|k| (1 + (7 * k) % members.len()) as u128 * 10 * EDG).collect();
+    let tippers: Vec<(sr25519::Pair, u128)> = councillors.iter().cloned().zip(tip_values.iter().cloned()).collect();
+    // A finder reports a tip.
+    let reason = b"Edgeware node tests tip report".to_vec();
+    let reported = report(api, finder, &reason, &who).await?;
+    let tx = polkadot::tx().tips().report_awesome(reason.clone(), who.clone());
+    errors::assert_module_error(api, submit(api, finder, &tx).await, "AlreadyKnown");
+    // A councillor opens a tip, with no deposit and no finder's fee.
+    let other_reason = b"Edgeware node tests council tip".to_vec();
+    let tx = polkadot::tx().tips().tip_new(other_reason.clone(), other_who.clone(), tip_values[0]);
+    let events = submit(api, &councillors[0], &tx).await?;
+    let new_tip = events.find_first::<polkadot::tips::events::NewTip>()?
+        .expect("ERROR: Tip incorrectly registered");
+    let opened = tip_hash(&other_reason, &other_who);
+    assert_eq!(new_tip.tip_hash, opened);
+    let tip = api.storage().fetch(&polkadot::storage().tips().tips(&opened), Some(events.block_hash())).await?
+        .expect("ERROR: Tip incorrectly registered");
+    assert_eq!(tip.finder, members[0]);
+    assert_eq!(tip.deposit, 0);
+    assert!(!tip.finders_fee);
+    assert_eq!(tip.tips.len(), 1);
+    println!("Tip opened by councillor 0 for {}: {:?}",other_who, opened);
+    // The councillors tip both, which can be closed once the countdown is over.
+    let closes = tip_all(api, &tippers, reported, members.len()).await?;
+    let other_closes = tip_all(api, &tippers[1..], opened, members.len()).await?;
+    let now = blocks::block_number(api, None).await?;
+    if now < closes {
+        errors::assert_module_error(api, submit(api, closer, &polkadot::tx().tips().close_tip(reported)).await, "Premature");
+    }
+    let now = blocks::block_number(api, None).await?;
+    let last = closes.max(other_closes);
+    if last >= now {
+        blocks::wait_blocks(api, last - now + 1).await?;
+    }
+    close(api, closer, reported, &tip_values).await?;
+    close(api, closer, opened, &tip_values).await?;
+    // Only the finder can retract a reported tip, getting the deposit back.
+    let reason = b"Edgeware node tests retracted tip".to_vec();
+    let retracted = report(api, finder, &reason, &who).await?;
+    errors::assert_module_error(api, submit(api, closer, &polkadot::tx().tips().retract_tip(retracted)).await, "NotFinder");
+    let events = submit(api, finder, &polkadot::tx().tips().retract_tip(retracted)).await?;
+    let event = events.find_first::<polkadot::tips::events::TipRetracted>()?
+        .expect("ERROR: Tip not retracted");
+    assert_eq!(event.tip_hash, retracted);
+    let (_, reserved) = balances::balance_deltas(api, &finder_id, events.block_hash()).await?;
+    assert_eq!(reserved, -(report_deposit(api, &reason)? as i128));
+    let remaining = api.storage().fetch(&polkadot::storage().tips().tips(&retracted), Some(events.block_hash())).await?;
+    assert!(remaining.is_none());
+    println!("Tip {:?} retracted by its finder",retracted);
+    // Root slashes a reported tip, forfeiting the deposit.
+    let reason = b"Edgeware node tests slashed tip".to_vec();
+    let slashed = report(api, finder, &reason, &who).await?;
+    let call = Call::Tips(TipsCall::slash_tip { hash: slashed });
+    let events = sudo(api, sudo_seed_account, call).await?;
+    let event = events.find_first::<polkadot::tips::events::TipSlashed>()?
+        .expect("ERROR: Tip not slashed");
+    let deposit = report_deposit(api, &reason)?;
+    assert_eq!(event.tip_hash, slashed);
+    assert_eq!(event.finder, finder_id);
+    assert_eq!(event.deposit, deposit);
+    let (free, reserved) = balances::balance_deltas(api, &finder_id, events.block_hash()).await?;
+    assert_eq!((free, reserved), (0, -(deposit as i128)));
+    let remaining = api.storage().fetch(&polkadot::storage().tips().tips(&slashed), Some(events.block_hash())).await?;
+    assert!(remaining.is_none());
+    println!("Tip {:?} slashed, deposit {} forfeited",slashed, deposit);
+    Ok(())
+}
@@ -0,0 +1,165 @@
+use subxt::{
+    ext::{
+        sp_core::sr25519,
+        sp_runtime::AccountId32,
+    },
+    OnlineClient,
+    PolkadotConfig,
+};
+use crate::consts::*;
+use crate::{balances, blocks, scheduler};
+use crate::tx::{submit, sudo};
+
+#[subxt::subxt(runtime_metadata_path = "./data/metadata.scale")]
+pub mod polkadot {}
+
+type Call = polkadot::runtime_types::edgeware_runtime::Call;
+type TreasuryCall = polkadot::runtime_types::pallet_treasury::pallet::Call;
+
+/// Account of the treasury pot, derived from the treasury pallet id.
+pub fn treasury_account(api: &OnlineClient<PolkadotConfig>) -> Result<AccountId32, Box<dyn std::error::Error>> {
+    let pallet_id = api.constants().at(&polkadot::constants().treasury().pallet_id())?;
+    let mut raw = [0u8; 32];
+    raw[..4].copy_from_slice(b"modl");
+    raw[4..12].copy_from_slice(&pallet_id.0);
+    Ok(AccountId32::from(raw))
+}
+
+/// Spendable funds of the treasury pot.
+pub async fn pot(api: &OnlineClient<PolkadotConfig>) -> Result<u128, Box<dyn std::error::Error>> {
+    let existential_deposit = api.constants().at(&polkadot::constants().balances().existential_deposit())?;
+    let (free, ..) = balances::account_data(api, &treasury_account(api)?, None).await?;
+    Ok(free.saturating_sub(existential_deposit))
+}
+
+/// Tops the treasury pot up to `amount` from the sudo account.
+pub async fn fund(api: &OnlineClient<PolkadotConfig>, sudo_seed_account: &sr25519::Pair, amount: u128) -> Result<(), Box<dyn std::error::Error>> {
+    let pot = pot(api).await?;
+    if pot < amount {
+        let tx = polkadot::tx().balances().transfer(treasury_account(api)?.into(), amount - pot);
+        submit(api, sudo_seed_account, &tx).await?;
+        println!("Funded the treasury with {}",amount - pot);
+    }
+    Ok(())
+}
+
+/// `parts` millionths of `value`, rounded as the runtime's `Permill` multiplication does.
+pub fn permill_of(parts: u32, value: u128) -> u128 {
+    let parts = parts as u128;
+    value / 1_000_000 * parts + (value % 1_000_000 * parts + 499_999) / 1_000_000
+}
+
+/// Bond reserved from the proposer of a spend of `value`.
+pub fn proposal_bond(api: &OnlineClient<PolkadotConfig>, value: u128) -> Result<u128, Box<dyn std::error::Error>> {
+    let bond = api.constants().at(&polkadot::constants().treasury().proposal_bond())?;
+    let bond_minimum = api.constants().at(&polkadot::constants().treasury().proposal_bond_minimum())?;
+    let bond_maximum = api.constants().at(&polkadot::constants().treasury().proposal_bond_maximum())?;
+    let bond = permill_of(bond.0, value).max(bond_minimum);
+    Ok(bond_maximum.map_or(bond, |m| bond.min(m)))
+}
+
+/// Follows a treasury spend proposal from the enactment of the referendum approving it
+/// to its payout at the next spend period.
+pub async fn verify_payout(api: &OnlineClient<PolkadotConfig>, proposal_index: u32, ref_index: u32) -> Result<(), Box<dyn std::error::Error>> {
+    let proposal = api.storage().fetch(&polkadot::storage().treasury().proposals(&proposal_index), None).await?
+        .expect("ERROR: Treasury proposal incorrectly registered");
+    let voting_period = api.constants().at(&polkadot::constants().democracy().voting_period())?;
+    let enactment_period = api.constants().at(&polkadot::constants().democracy().enactment_period())?;
+    let spend_period = api.constants().at(&polkadot::constants().treasury().spend_period())?;
+    // The referendum enacts `approve_proposal`.
+    let (executed, hash) = blocks::wait_for_matching_event::<polkadot::democracy::events::Executed, _>(
+        api,
+        voting_period + enactment_period + 2,
+        |e| e.ref_index == ref_index,
+    ).await?.expect("ERROR: Treasury approval referendum not enacted");
+    assert!(executed.result.is_ok());
+    scheduler::verify_enactment(api, ref_index, hash).await?;
+    let approvals = api.storage().fetch_or_default(&polkadot::storage().treasury().approvals(), Some(hash)).await?;
+    assert!(approvals.0.contains(&proposal_index));
+    println!("Treasury proposal {} approved by referendum {}",proposal_index, ref_index);
+    // The proposal is paid out at the next spend period and the bond is returned.
+    let (awarded, hash) = blocks::wait_for_matching_event::<polkadot::treasury::events::Awarded, _>(
+        api,
+        spend_period + 2,
+        |e| e.proposal_index == proposal_index,
+    ).await?.expect("ERROR: Treasury proposal not paid out");
+    assert_eq!(awarded.award, proposal.value);
+    assert_eq!(awarded.account, proposal.beneficiary);
+    let (free, _) = balances::balance_deltas(api, &proposal.beneficiary, hash).await?;
+    assert_eq!(free, proposal.value as i128);
+    let (_, reserved) = balances::balance_deltas(api, &proposal.proposer, hash).await?;
+    assert_eq!(reserved, -(proposal.bond as i128));
+    let remaining = api.storage().fetch(&polkadot::storage().treasury().proposals(&proposal_index), Some(hash)).await?;
+    assert!(remaining.is_none());
+    let approvals = api.storage().fetch_or_default(&polkadot::storage().treasury().approvals(), Some(hash)).await?;
+    assert!(!approvals.0.contains(&proposal_index));
+    println!("Treasury proposal {} paid {} to {}, bond {} returned",proposal_index, awarded.award, awarded.account, proposal.bond);
+    Ok(())
+}
+
+/// Rejects a spend proposal through root, checking that the bond goes to the treasury,
+/// then reconciles the pot with the spend, burn and rollover of the next spend period.
+pub async fn rejection_and_spend_period(api: &OnlineClient<PolkadotConfig>, acc_seed_accounts : &[sr25519::Pair], sudo_seed_account: &sr25519::Pair) -> Result<(), Box<dyn std::error::Error>> {
+    // User 11 proposes a spend to user 1.
+    let i = 11;
+    let value = TEST_ACCOUNT_FUNDING / 10;
+    let acc_id: AccountId32 = acc_seed_accounts[i].public().into();
+    let beneficiary: AccountId32 = acc_seed_accounts[1].public().into();
+    let tx = polkadot::tx().treasury().propose_spend(value, beneficiary.into());
+    let events = submit(api, &acc_seed_accounts[i], &tx).await?;
+    let proposed = events.find_first::<polkadot::treasury::events::Proposed>()?
+        .expect("ERROR: Treasury proposal incorrectly registered");
+    let bond = proposal_bond(api, value)?;
+    let (_, reserved) = balances::balance_deltas(api, &acc_id, events.block_hash()).await?;
+    assert_eq!(reserved, bond as i128);
+    println!("Treasury proposal {} submitted for test account {}",proposed.proposal_index, i);
+    // Root rejects it and the bond is slashed to the treasury.
+    let call = Call::Treasury(TreasuryCall::reject_proposal { proposal_id: proposed.proposal_index });
+    let events = sudo(api, sudo_seed_account, call).await?;
+    let rejected = events.find_first::<polkadot::treasury::events::Rejected>()?
+        .expect("ERROR: Treasury proposal not rejected");
+    assert_eq!(rejected.proposal_index, proposed.proposal_index);
+    assert_eq!(rejected.slashed, bond);
+    let deposit = events.find_first::<polkadot::treasury::events::Deposit>()?
+        .expect("ERROR: Rejected treasury proposal bond not deposited in the treasury");
+    assert_eq!(deposit.value, bond);
+    assert_eq!(balances::balance_deltas(api, &acc_id, events.block_hash()).await?, (0, -(bond as i128)));
+    let remaining = api.storage().fetch(&polkadot::storage().treasury().proposals(&proposed.proposal_index), None).await?;
+    assert!(remaining.is_none());
+    println!("Treasury proposal {} rejected, bond {} forfeited",proposed.proposal_index, bond);
+    // At the next spend period the pot is spent, burnt and rolled over.
+    let spend_period = api.constants().at(&polkadot::constants().treasury().spend_period())?;
+    let burn = api.constants().at(&polkadot::constants().treasury().burn())?;
+    let (spending, hash) = blocks::wait_for_matching_event::<polkadot::treasury::events::Spending, _>(api, spend_period + 2, |_| true).await?
+        .expect("ERROR: No treasury spend period");
+    let parent = blocks::parent_hash(api, hash).await?;
+    let existential_deposit = api.constants().at(&polkadot::constants().balances().existential_deposit())?;
+    let (pot_free, ..) = balances::account_data(api, &treasury_account(api)?, Some(parent)).await?;
+    assert_eq!(spending.budget_remaining, pot_free.saturating_sub(existential_deposit));
+    let events = api.events().at(Some(hash)).await?;
+    let mut spent = 0;
+    for awarded in events.find::<polkadot::treasury::events::Awarded>() {
+        spent += awarded?.award;
+    }
+    // Bounties are funded from the same budget.
+    for active in events.find::<polkadot::bounties::events::BountyBecameActive>() {
+        let bounty = api.storage().fetch(&polkadot::storage().bounties().bounties(&active?.index), Some(hash)).await?
+            .expect("ERROR: Active bounty incorrectly registered");
+        spent += bounty.value;
+    }
+    let rollover = events.find_first::<polkadot::treasury::events::Rollover>()?
+        .expect("ERROR: Treasury budget not rolled over");
+    match events.find_first::<polkadot::treasury::events::Burnt>()? {
+        Some(burnt) => {
+            assert_eq!(burnt.burnt_funds, permill_of(burn.0, spending.budget_remaining - spent));
+            assert_eq!(rollover.rollover_balance + burnt.burnt_funds + spent, spending.budget_remaining);
+        },
+        // Nothing is burnt when some approved proposal could not be paid.
+        None => assert!(rollover.rollover_balance + spent <= spending.budget_remaining),
+    }
+    // What is rolled over is all that is left in the pot.
+    let (pot_free, ..) = balances::account_data(api, &treasury_account(api)?, Some(hash)).await?;
+    assert_eq!(pot_free, rollover.rollover_balance + existential_deposit);
+    println!("Treasury spend period: budget {}, spent {}, rollover {}",spending.budget_remaining, spent, rollover.rollover_balance);
+    Ok(())
+}
@@ -0,0 +1,40 @@
+use codec::{Decode, Encode};
+use subxt::{
+    tx::{PairSigner, TxEvents, TxPayload},
+    ext::sp_core::sr25519,
+    OnlineClient,
+    PolkadotConfig,
+};
+use crate::errors;
+
+#[subxt::subxt(runtime_metadata_path = "./data/metadata.scale")]
+pub mod polkadot {}
+
+type Call = polkadot::runtime_types::edgeware_runtime::Call;
+
+/// Signs and submits `tx`, waiting for its successful finalization.
+pub async fn submit<P: TxPayload>(api: &OnlineClient<PolkadotConfig>, signer: &sr25519::Pair, tx: &P) -> Result<TxEvents<PolkadotConfig>, subxt::Error> {
+    api.tx()
+        .sign_and_submit_then_watch_default(tx, &PairSigner::new(signer.clone()))
+        .await?
+        .wait_for_finalized_success()
+        .await
+}
+
+/// Dispatches `call` from root, returning the events and the name of the error if it failed. The
+/// call can be built with the runtime types of any module, they all share the same encoding.
+pub async fn sudo_result<C: Encode>(api: &OnlineClient<PolkadotConfig>, sudo_seed_account: &sr25519::Pair, call: C) -> Result<(TxEvents<PolkadotConfig>, Result<(), String>), Box<dyn std::error::Error>> {
+    let call = Call::decode(&mut &call.encode()[..]).expect("all modules are generated from the same metadata; qed");
+    let events = submit(api, sudo_seed_account, &polkadot::tx().sudo().sudo(call)).await?;
+    let sudid = events.find_first::<polkadot::sudo::events::Sudid>()?
+        .expect("ERROR: Root call not dispatched");
+    let result = sudid.sudo_result.map_err(|e| errors::dispatch_error_name(api, &e.encode()));
+    Ok((events, result))
+}
+
+/// Dispatches `call` from root and checks that it succeeded.
+pub async fn sudo<C: Encode>(api: &OnlineClient<PolkadotConfig>, sudo_seed_account: &sr25519::Pair, call: C) -> Result<TxEvents<PolkadotConfig>, Box<dyn std::error::Error>> {
+    let (events, result) = sudo_result(api, sudo_seed_account, call).await?;
+    assert_eq!(result, Ok(()), "ERROR: Root call failed");
+    Ok(events)
+}
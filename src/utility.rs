@@ -0,0 +1,86 @@
+use codec::{Decode, Encode};
+use subxt::{
+    tx::TxEvents,
+    ext::{
+        sp_core::{blake2_256, sr25519, Pair},
+        sp_runtime::AccountId32,
+    },
+    OnlineClient,
+    PolkadotConfig,
+};
+use crate::consts::*;
+use crate::{balances, errors};
+use crate::tx::submit;
+
+#[subxt::subxt(runtime_metadata_path = "./data/metadata.scale")]
+pub mod polkadot {}
+
+type Call = polkadot::runtime_types::edgeware_runtime::Call;
+type BalancesCall = polkadot::runtime_types::pallet_balances::pallet::Call;
+type SystemCall = polkadot::runtime_types::frame_system::pallet::Call;
+
+/// Account on whose behalf `as_derivative` dispatches calls of `who` with the given index.
+pub fn derivative_account(who: &AccountId32, index: u16) -> AccountId32 {
+    let entropy = blake2_256(&(b"modlpy/utilisuba", who, index).encode());
+    AccountId32::decode(&mut &entropy[..]).expect("an account id is 32 bytes; qed")
+}
+
+/// Number of `ItemCompleted` events of a batch.
+fn completed_items(events: &TxEvents<PolkadotConfig>) -> usize {
+    events.find::<polkadot::utility::events::ItemCompleted>().count()
+}
+
+/// Dispatches batches of successful and failing transfers between test accounts, checking which
+/// calls of each kind of batch keep their effect, then dispatches a call from a derivative account.
+/// The runtime predates `force_batch`, so there is no `BatchCompletedWithErrors` to check.
+pub async fn batch_semantics(api: &OnlineClient<PolkadotConfig>, acc_seed_accounts : &[sr25519::Pair]) -> Result<(), Box<dyn std::error::Error>> {
+    let signer = &acc_seed_accounts[47];
+    let signer_id: AccountId32 = signer.public().into();
+    let dest: AccountId32 = acc_seed_accounts[48].public().into();
+    let transfer = |value: u128| Call::Balances(BalancesCall::transfer { dest: dest.clone().into(), value });
+    let failing = || Call::Balances(BalancesCall::transfer { dest: dest.clone().into(), value: 10 * TEST_ACCOUNT_FUNDING });
+    let root_only = || Call::System(SystemCall::set_heap_pages { pages: 64 });
+    // `batch` keeps the calls before the first failure and skips the others.
+    let (free_before, ..) = balances::account_data(api, &dest, None).await?;
+    let events = submit(api, signer, &polkadot::tx().utility().batch(vec![transfer(EDG), failing(), transfer(2 * EDG)])).await?;
+    let interrupted = events.find_first::<polkadot::utility::events::BatchInterrupted>()?
+        .expect("ERROR: Batch not interrupted");
+    assert_eq!(interrupted.index, 1);
+    assert_eq!(errors::dispatch_error_name(api, &interrupted.error.encode()), "InsufficientBalance");
+    assert_eq!(completed_items(&events), 1);
+    assert!(!events.has::<polkadot::utility::events::BatchCompleted>()?);
+    let (free_after, ..) = balances::account_data(api, &dest, Some(events.block_hash())).await?;
+    assert_eq!(free_after - free_before, EDG);
+    // Calls of a batch are dispatched with the origin of the batch.
+    let events = submit(api, signer, &polkadot::tx().utility().batch(vec![root_only(), transfer(EDG)])).await?;
+    let interrupted = events.find_first::<polkadot::utility::events::BatchInterrupted>()?
+        .expect("ERROR: Batch not interrupted");
+    assert_eq!((interrupted.index, errors::dispatch_error_name(api, &interrupted.error.encode())), (0, "BadOrigin".to_owned()));
+    assert_eq!(completed_items(&events), 0);
+    let events = submit(api, signer, &polkadot::tx().utility().batch(vec![transfer(EDG), transfer(2 * EDG)])).await?;
+    assert!(events.has::<polkadot::utility::events::BatchCompleted>()?);
+    assert_eq!(completed_items(&events), 2);
+    println!("Batch of test account 47 interrupted at its failing call");
+    // `batch_all` reverts all its calls if any fails.
+    let (free_before, ..) = balances::account_data(api, &dest, None).await?;
+    errors::assert_module_error(api, submit(api, signer, &polkadot::tx().utility().batch_all(vec![transfer(EDG), failing(), transfer(2 * EDG)])).await, "InsufficientBalance");
+    let (free_after, ..) = balances::account_data(api, &dest, None).await?;
+    assert_eq!(free_after, free_before);
+    let events = submit(api, signer, &polkadot::tx().utility().batch_all(vec![transfer(EDG), transfer(2 * EDG)])).await?;
+    assert!(events.has::<polkadot::utility::events::BatchCompleted>()?);
+    assert_eq!(completed_items(&events), 2);
+    let (free_after, ..) = balances::account_data(api, &dest, Some(events.block_hash())).await?;
+    assert_eq!(free_after - free_before, 3 * EDG);
+    println!("Failing batch_all of test account 47 reverted");
+    // Derivative accounts are distinct accounts controlled by their owner.
+    let index = 0;
+    let derivative = derivative_account(&signer_id, index);
+    submit(api, signer, &polkadot::tx().balances().transfer(derivative.clone().into(), 10 * EDG)).await?;
+    let events = submit(api, signer, &polkadot::tx().utility().as_derivative(index, transfer(EDG))).await?;
+    let transferred = events.find_first::<polkadot::balances::events::Transfer>()?
+        .expect("ERROR: Derivative call not dispatched");
+    assert_eq!((transferred.from, transferred.to, transferred.amount), (derivative.clone(), dest.clone(), EDG));
+    errors::assert_module_error(api, submit(api, signer, &polkadot::tx().utility().as_derivative(index, failing())).await, "InsufficientBalance");
+    println!("Derivative account {} of test account 47 dispatched a transfer",derivative);
+    Ok(())
+}
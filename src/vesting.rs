@@ -0,0 +1,201 @@
+use std::cmp::max;
+use std::collections::HashMap;
+use codec::Encode;
+use subxt::{
+    ext::{
+        sp_core::{sr25519, Pair, H256},
+        sp_runtime::AccountId32,
+    },
+    OnlineClient,
+    PolkadotConfig,
+};
+use crate::consts::*;
+use crate::{blocks, errors};
+use crate::tx::submit;
+
+#[subxt::subxt(runtime_metadata_path = "./data/metadata.scale")]
+pub mod polkadot {}
+
+type Call = polkadot::runtime_types::edgeware_runtime::Call;
+type VestingCall = polkadot::runtime_types::pallet_vesting::pallet::Call;
+type VestingInfo = polkadot::runtime_types::pallet_vesting::vesting_info::VestingInfo<u128, u32>;
+
+/// Amount of a schedule still locked at block `n`.
+pub fn locked_at(schedule: &VestingInfo, n: u32) -> u128 {
+    let vested = max(schedule.per_block, 1).saturating_mul(n.saturating_sub(schedule.starting_block) as u128);
+    schedule.locked.saturating_sub(vested)
+}
+
+/// Block at which a schedule is fully unlocked.
+pub fn ending_block(schedule: &VestingInfo) -> u128 {
+    let per_block = max(schedule.per_block, 1);
+    let duration = if per_block >= schedule.locked {
+        1
+    } else {
+        schedule.locked / per_block + if schedule.locked % per_block == 0 { 0 } else { 1 }
+    };
+    duration + schedule.starting_block as u128
+}
+
+/// Schedule replacing two schedules merged at block `now`, as `merge_schedules` computes it,
+/// or `None` if both have ended.
+pub fn merged(s1: &VestingInfo, s2: &VestingInfo, now: u32) -> Option<VestingInfo> {
+    let copy = |s: &VestingInfo| VestingInfo { locked: s.locked, per_block: s.per_block, starting_block: s.starting_block };
+    match (ending_block(s1) <= now as u128, ending_block(s2) <= now as u128) {
+        (true, true) => return None,
+        (true, false) => return Some(copy(s2)),
+        (false, true) => return Some(copy(s1)),
+        (false, false) => {},
+    }
+    let locked = locked_at(s1, now) + locked_at(s2, now);
+    let starting_block = max(now, max(s1.starting_block, s2.starting_block));
+    let duration = max(max(ending_block(s1), ending_block(s2)).saturating_sub(starting_block as u128), 1);
+    Some(VestingInfo { locked, per_block: max(locked / duration, 1), starting_block })
+}
+
+/// Amount locked by vesting on an account.
+async fn vesting_lock(api: &OnlineClient<PolkadotConfig>, acc_id: &AccountId32, at: Option<H256>) -> Result<u128, Box<dyn std::error::Error>> {
+    let locks = api.storage().fetch_or_default(&polkadot::storage().balances().locks(acc_id), at).await?;
+    Ok(locks.0.iter().filter(|l| &l.id == b"vesting ").map(|l| l.amount).sum())
+}
+
+/// Checks the vesting lock of an account against its schedules in the block with the given hash.
+async fn check_lock(api: &OnlineClient<PolkadotConfig>, acc_id: &AccountId32, hash: H256) -> Result<u128, Box<dyn std::error::Error>> {
+    let number = blocks::block_number(api, Some(hash)).await?;
+    let schedules = api.storage().fetch(&polkadot::storage().vesting().vesting(acc_id), Some(hash)).await?
+        .map_or(Vec::new(), |s| s.0);
+    let locked: u128 = schedules.iter().map(|s| locked_at(s, number)).sum();
+    assert_eq!(vesting_lock(api, acc_id, Some(hash)).await?, locked);
+    Ok(locked)
+}
+
+/// Makes a vested transfer from `source`, checking the lock of the target.
+async fn vested_transfer(api: &OnlineClient<PolkadotConfig>, source: &sr25519::Pair, target: &AccountId32, schedule: VestingInfo) -> Result<(), Box<dyn std::error::Error>> {
+    let tx = polkadot::tx().vesting().vested_transfer(target.clone().into(), schedule);
+    let events = submit(api, source, &tx).await?;
+    let updated = events.find_first::<polkadot::vesting::events::VestingUpdated>()?
+        .expect("ERROR: Vesting not updated");
+    let locked = check_lock(api, target, events.block_hash()).await?;
+    assert_eq!((&updated.account, updated.unvested), (target, locked));
+    Ok(())
+}
+
+/// Makes vested transfers to test accounts with schedules starting at various blocks and rates,
+/// checking the transfers below `MinVestedTransfer` or with invalid schedules are rejected.
+pub async fn create_schedules(api: &OnlineClient<PolkadotConfig>, acc_seed_accounts : &[sr25519::Pair]) -> Result<(), Box<dyn std::error::Error>> {
+    let source = &acc_seed_accounts[38];
+    let (target, far_target): (AccountId32, AccountId32) = (acc_seed_accounts[39].public().into(), acc_seed_accounts[40].public().into());
+    let min_transfer = api.constants().at(&polkadot::constants().vesting().min_vested_transfer())?;
+    let number = blocks::block_number(api, None).await?;
+    let tx = polkadot::tx().vesting().vested_transfer(target.clone().into(), VestingInfo { locked: min_transfer - 1, per_block: EDG, starting_block: number });
+    errors::assert_module_error(api, submit(api, source, &tx).await, "AmountLow");
+    let tx = polkadot::tx().vesting().vested_transfer(target.clone().into(), VestingInfo { locked: min_transfer, per_block: 0, starting_block: number });
+    errors::assert_module_error(api, submit(api, source, &tx).await, "InvalidScheduleParams");
+    // A fast schedule starting later and a slow one already started.
+    vested_transfer(api, source, &target, VestingInfo { locked: 10 * min_transfer, per_block: min_transfer / 10, starting_block: number + 10 }).await?;
+    vested_transfer(api, source, &target, VestingInfo { locked: 5 * min_transfer, per_block: min_transfer / 100, starting_block: number }).await?;
+    // A schedule which does not start before the end of the tests.
+    vested_transfer(api, source, &far_target, VestingInfo { locked: min_transfer, per_block: EDG, starting_block: number + 1_000_000 }).await?;
+    println!("Vesting schedules created for test accounts 39 and 40");
+    Ok(())
+}
+
+/// Encoded vesting schedules of the test accounts.
+pub async fn dump_schedules(api: &OnlineClient<PolkadotConfig>, acc_seed_accounts : &[sr25519::Pair]) -> Result<HashMap<AccountId32, Vec<u8>>, Box<dyn std::error::Error>> {
+    let mut schedules = HashMap::new();
+    for acc in acc_seed_accounts.iter() {
+        let acc_id: AccountId32 = acc.public().into();
+        if let Some(s) = api.storage().fetch(&polkadot::storage().vesting().vesting(&acc_id), None).await? {
+            schedules.insert(acc_id, s.encode());
+        }
+    }
+    Ok(schedules)
+}
+
+/// Checks the vesting schedules of the test accounts are the ones recorded before the upgrade.
+pub async fn verify_schedules(api: &OnlineClient<PolkadotConfig>, acc_seed_accounts : &[sr25519::Pair], before: &HashMap<AccountId32, Vec<u8>>) -> Result<(), Box<dyn std::error::Error>> {
+    let after = dump_schedules(api, acc_seed_accounts).await?;
+    for (acc_id, schedules) in before.iter() {
+        match after.get(acc_id) {
+            Some(s) if s == schedules => {},
+            Some(_) => panic!("ERROR: Vesting schedules of {} changed by the upgrade", acc_id),
+            None => panic!("ERROR: Vesting schedules of {} removed by the upgrade", acc_id),
+        }
+    }
+    assert_eq!(after.len(), before.len(), "ERROR: Vesting schedules created by the upgrade");
+    println!("Vesting schedules of {} accounts preserved by the upgrade",before.len());
+    Ok(())
+}
+
+/// Vests the schedules created by `create_schedules` at several points, checking the lock declines,
+/// then merges them and fills the schedules of an account up to `MaxVestingSchedules`.
+pub async fn vest_and_merge(api: &OnlineClient<PolkadotConfig>, acc_seed_accounts : &[sr25519::Pair]) -> Result<(), Box<dyn std::error::Error>> {
+    let (source, holder) = (&acc_seed_accounts[38], &acc_seed_accounts[39]);
+    let holder_id: AccountId32 = holder.public().into();
+    let far_target: AccountId32 = acc_seed_accounts[40].public().into();
+    let min_transfer = api.constants().at(&polkadot::constants().vesting().min_vested_transfer())?;
+    let max_schedules = api.constants().at(&polkadot::constants().vesting().max_vesting_schedules())?;
+    // The lock declines as blocks pass, whoever vests.
+    let mut previous = None;
+    for i in 0..3 {
+        let events = if i % 2 == 0 {
+            submit(api, holder, &polkadot::tx().vesting().vest()).await?
+        } else {
+            submit(api, source, &polkadot::tx().vesting().vest_other(holder_id.clone().into())).await?
+        };
+        let locked = check_lock(api, &holder_id, events.block_hash()).await?;
+        if let Some(previous) = previous {
+            assert!(locked < previous || locked == 0);
+        }
+        previous = Some(locked);
+        println!("Test account 39 vested, {} still locked",locked);
+        blocks::wait_blocks(api, 3).await?;
+    }
+    // Merging schedules keeps the locked amount and the latest end.
+    let schedules = api.storage().fetch(&polkadot::storage().vesting().vesting(&holder_id), None).await?
+        .map_or(Vec::new(), |s| s.0);
+    assert_eq!(schedules.len(), 2, "ERROR: Test account 39 does not have its 2 vesting schedules");
+    let events = submit(api, holder, &polkadot::tx().vesting().merge_schedules(0, 1)).await?;
+    let number = blocks::block_number(api, Some(events.block_hash())).await?;
+    let after = api.storage().fetch(&polkadot::storage().vesting().vesting(&holder_id), Some(events.block_hash())).await?
+        .map_or(Vec::new(), |s| s.0);
+    match merged(&schedules[0], &schedules[1], number) {
+        Some(expected) => {
+            assert_eq!(after.len(), 1);
+            assert_eq!((after[0].locked, after[0].per_block, after[0].starting_block), (expected.locked, expected.per_block, expected.starting_block));
+        },
+        None => assert!(after.is_empty()),
+    }
+    check_lock(api, &holder_id, events.block_hash()).await?;
+    errors::assert_module_error(api, submit(api, holder, &polkadot::tx().vesting().merge_schedules(0, 1)).await, "ScheduleIndexOutOfBounds");
+    println!("Vesting schedules of test account 39 merged");
+    // A schedule unlocking in a block completes the vesting.
+    let completed: AccountId32 = acc_seed_accounts[41].public().into();
+    let number = blocks::block_number(api, None).await?;
+    vested_transfer(api, source, &completed, VestingInfo { locked: min_transfer, per_block: min_transfer, starting_block: number + 5 }).await?;
+    while blocks::block_number(api, None).await? <= number + 6 {
+        blocks::wait_blocks(api, 1).await?;
+    }
+    let events = submit(api, &acc_seed_accounts[41], &polkadot::tx().vesting().vest()).await?;
+    let done = events.find_first::<polkadot::vesting::events::VestingCompleted>()?
+        .expect("ERROR: Vesting not completed");
+    assert_eq!(done.account, completed);
+    assert!(api.storage().fetch(&polkadot::storage().vesting().vesting(&completed), Some(events.block_hash())).await?.is_none());
+    assert_eq!(vesting_lock(api, &completed, Some(events.block_hash())).await?, 0);
+    errors::assert_module_error(api, submit(api, &acc_seed_accounts[41], &polkadot::tx().vesting().vest()).await, "NotVesting");
+    // Accounts have at most MaxVestingSchedules schedules.
+    let nb_schedules = api.storage().fetch(&polkadot::storage().vesting().vesting(&far_target), None).await?
+        .map_or(0, |s| s.0.len());
+    let calls = (nb_schedules..max_schedules as usize)
+        .map(|_| Call::Vesting(VestingCall::vested_transfer { target: far_target.clone().into(), schedule: VestingInfo { locked: min_transfer, per_block: EDG, starting_block: number + 1_000_000 } }))
+        .collect();
+    submit(api, source, &polkadot::tx().utility().batch_all(calls)).await?;
+    let tx = polkadot::tx().vesting().vested_transfer(far_target.clone().into(), VestingInfo { locked: min_transfer, per_block: EDG, starting_block: number + 1_000_000 });
+    errors::assert_module_error(api, submit(api, source, &tx).await, "AtMaxVestingSchedules");
+    let schedules = api.storage().fetch(&polkadot::storage().vesting().vesting(&far_target), None).await?
+        .expect("ERROR: Vesting schedules removed");
+    assert_eq!(schedules.0.len(), max_schedules as usize);
+    assert_eq!(vesting_lock(api, &far_target, None).await?, max_schedules as u128 * min_transfer);
+    println!("Test account 40 holds {} vesting schedules",max_schedules);
+    Ok(())
+}